@@ -1,62 +1,429 @@
-use std::env::args;
+use num_bigint::BigUint;
+use std::{
+    collections::HashMap,
+    env::args,
+    fs::File,
+    io::{BufWriter, Write},
+    mem,
+};
+
+// Iterations simulated directly before splitting into elements, as the splitting rules only
+// hold for strings that are at least two days old.
+const SPLIT_AGE: usize = 2;
+
+// The limit of the ratio between successive lengths, for any seed other than "22".
+const CONWAYS_CONSTANT: f64 = 1.3035772690342964;
+
+// Digits for writing counts in bases up to 36.
+const COUNT_DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
 
 fn main() {
     let pargs = Vec::from_iter(args());
-    if pargs.len() != 2 {
-        eprintln!("Please specify 'part1' or 'part2'");
+    if pargs.len() < 2 {
+        eprintln!("Please specify 'part1', 'part2', 'elements' or 'check'");
         return;
     }
-    let input = "1113122113".to_string();
-    match pargs[1].as_str() {
-        "part1" => {
-            println!("{}", part1(input))
+    let options = match parse_options(&pargs[2..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
+        }
+    };
+    let input = options
+        .seed
+        .clone()
+        .unwrap_or_else(|| "1113122113".to_string());
+    let lengths = match pargs[1].as_str() {
+        "part1" => part1(input, &options),
+        "part2" => part2(input, &options),
+        "elements" | "check" if options.iterations.is_none() => {
+            eprintln!("Please specify a number of iterations, e.g. '--iterations 1000'");
+            return;
+        }
+        "elements" | "check" if !options.variant.is_standard() => {
+            eprintln!("Elements only apply to the standard sequence.");
+            return;
         }
-        "part2" => {
-            println!("{}", part2(input))
+        "elements" => element_lengths(&input, options.iterations.unwrap()),
+        "check" => {
+            let iterations = options.iterations.unwrap();
+            match check_elements(&input, iterations) {
+                Ok(()) => println!("Lengths match for 0 to {} iterations", iterations),
+                Err(message) => eprintln!("{}", message),
+            }
+            return;
         }
         _ => {
-            eprintln!("Invalid argument.")
+            eprintln!("Invalid argument.");
+            return;
         }
     };
+    if options.series {
+        print_series(&lengths);
+        if options.variant.is_standard() {
+            println!("Conway's constant: {:.9}", CONWAYS_CONSTANT);
+        }
+    } else {
+        println!("{}", lengths.last().unwrap());
+    }
+    if let Some(path) = &options.csv {
+        if let Err(message) = save_series(&lengths, path) {
+            eprintln!("{}", message);
+        }
+    }
 }
 
-fn part1(input: String) -> u32 {
-    let mut chars = input.chars().collect();
-    for _ in 0..40 {
-        chars = look_and_say(chars);
+// Whether each count is said before or after the digit it counts.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum SayOrder {
+    #[default]
+    CountFirst,
+    DigitFirst,
+}
+
+// Whether to count each run of a digit, or every occurrence of it in the term as the pea
+// pattern does.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Grouping {
+    #[default]
+    Runs,
+    Totals,
+}
+
+// The rules for reading one term aloud to make the next. Digits can be any ASCII character,
+// but counts are written in the given base.
+#[derive(Clone, Copy)]
+struct Variant {
+    base: usize,
+    order: SayOrder,
+    grouping: Grouping,
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant {
+            base: 10,
+            order: SayOrder::default(),
+            grouping: Grouping::default(),
+        }
     }
-    chars.len() as u32
 }
 
-fn part2(input: String) -> u32 {
-    let mut chars = input.chars().collect();
-    for _ in 0..50 {
-        chars = look_and_say(chars);
+impl Variant {
+    fn is_standard(&self) -> bool {
+        self.base == 10 && self.order == SayOrder::CountFirst && self.grouping == Grouping::Runs
+    }
+
+    fn say(&self, output: &mut Vec<u8>, count: usize, digit: u8) {
+        match self.order {
+            SayOrder::CountFirst => {
+                self.push_count(output, count);
+                output.push(digit);
+            }
+            SayOrder::DigitFirst => {
+                output.push(digit);
+                self.push_count(output, count);
+            }
+        }
+    }
+
+    fn push_count(&self, output: &mut Vec<u8>, mut count: usize) {
+        let start = output.len();
+        loop {
+            output.push(COUNT_DIGITS[count % self.base]);
+            count /= self.base;
+            if count == 0 {
+                break;
+            }
+        }
+        output[start..].reverse();
     }
-    chars.len() as u32
 }
 
-fn look_and_say(input: Vec<char>) -> Vec<char> {
-    let mut out_queue = Vec::new();
-    for input_char in input {
-        let last = out_queue.last_mut();
-        match last {
-            None => {
-                out_queue.push((input_char, 1u32));
+struct Options {
+    seed: Option<String>,
+    variant: Variant,
+    iterations: Option<usize>,
+    series: bool,
+    csv: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        seed: None,
+        variant: Variant::default(),
+        iterations: None,
+        series: false,
+        csv: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                let iterations = args
+                    .next()
+                    .and_then(|iterations| iterations.parse().ok())
+                    .ok_or("Please specify a number of iterations, e.g. '--iterations 40'")?;
+                options.iterations = Some(iterations);
+            }
+            "--seed" => {
+                let seed = args
+                    .next()
+                    .filter(|seed| !seed.is_empty() && seed.is_ascii())
+                    .ok_or("Please specify a seed of ASCII characters, e.g. '--seed 1'")?;
+                options.seed = Some(seed.clone());
+            }
+            "--base" => {
+                options.variant.base = args
+                    .next()
+                    .and_then(|base| base.parse().ok())
+                    .filter(|base| (2..=COUNT_DIGITS.len()).contains(base))
+                    .ok_or("Please specify a base from 2 to 36, e.g. '--base 3'")?;
             }
-            Some(entry) => {
-                if entry.0 == input_char {
-                    entry.1 += 1;
-                } else {
-                    out_queue.push((input_char, 1));
+            "--order" => {
+                options.variant.order = match args.next().map(|order| order.as_str()) {
+                    Some("count-first") => SayOrder::CountFirst,
+                    Some("digit-first") => SayOrder::DigitFirst,
+                    _ => return Err("Please specify 'count-first' or 'digit-first'".to_string()),
+                };
+            }
+            "--pea" => options.variant.grouping = Grouping::Totals,
+            "--series" => options.series = true,
+            "--csv" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify a CSV file, e.g. '--csv lengths.csv'")?;
+                options.csv = Some(path.clone());
+            }
+            _ => return Err(format!("Unknown option '{}'.", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn part1(input: String, options: &Options) -> Vec<BigUint> {
+    sequence_lengths(input, options.iterations.unwrap_or(40), &options.variant)
+}
+
+fn part2(input: String, options: &Options) -> Vec<BigUint> {
+    sequence_lengths(input, options.iterations.unwrap_or(50), &options.variant)
+}
+
+// Lengths of the sequence after each iteration, starting with the input, found by building it.
+fn sequence_lengths(input: String, iterations: usize, variant: &Variant) -> Vec<BigUint> {
+    let mut digits = input.into_bytes();
+    let mut next = Vec::new();
+    let mut lengths = vec![BigUint::from(digits.len())];
+    for _ in 0..iterations {
+        look_and_say(&digits, &mut next, variant);
+        mem::swap(&mut digits, &mut next);
+        lengths.push(BigUint::from(digits.len()));
+    }
+    lengths
+}
+
+// Lengths of the sequence after each iteration, starting with the input, found by splitting the
+// sequence into Conway's elements and counting how many of each there are rather than building
+// it.
+fn element_lengths(input: &str, iterations: usize) -> Vec<BigUint> {
+    let mut digits = input.as_bytes().to_vec();
+    let mut next = Vec::new();
+    let mut lengths = vec![BigUint::from(digits.len())];
+    for _ in 0..iterations.min(SPLIT_AGE) {
+        look_and_say(&digits, &mut next, &Variant::default());
+        mem::swap(&mut digits, &mut next);
+        lengths.push(BigUint::from(digits.len()));
+    }
+    if iterations <= SPLIT_AGE {
+        return lengths;
+    }
+
+    let mut elements = Elements::default();
+    let mut counts = Vec::new();
+    for element in split_elements(&digits) {
+        let id = elements.id(element);
+        if id >= counts.len() {
+            counts.resize(id + 1, BigUint::ZERO);
+        }
+        counts[id] += 1u32;
+    }
+    for _ in SPLIT_AGE..iterations {
+        let mut next_counts = Vec::new();
+        for (id, count) in counts.iter().enumerate() {
+            for &decay_id in elements.decay(id) {
+                if decay_id >= next_counts.len() {
+                    next_counts.resize(decay_id + 1, BigUint::ZERO);
                 }
+                next_counts[decay_id] += count;
             }
-        };
+        }
+        counts = next_counts;
+        let length = counts
+            .iter()
+            .enumerate()
+            .map(|(id, count)| count * elements.elements[id].len())
+            .sum();
+        lengths.push(length);
+    }
+    lengths
+}
+
+// Compares the element lengths against building the sequence for up to the given number of
+// iterations.
+fn check_elements(input: &str, iterations: usize) -> Result<(), String> {
+    let expected = sequence_lengths(input.to_string(), iterations, &Variant::default());
+    let lengths = element_lengths(input, iterations);
+    for (i, (length, expected)) in lengths.iter().zip(&expected).enumerate() {
+        if length != expected {
+            return Err(format!(
+                "Lengths differ after {} iterations: {} from elements, {} from the sequence",
+                i, length, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Ratio of one length to the previous one, using only their leading bits so that lengths too
+// large for a float still work.
+fn growth_ratio(previous: &BigUint, length: &BigUint) -> f64 {
+    let shift = previous.bits().saturating_sub(f64::MANTISSA_DIGITS as u64);
+    let leading_bits = |n: &BigUint| u128::try_from(n >> shift).unwrap() as f64;
+    leading_bits(length) / leading_bits(previous)
+}
+
+fn print_series(lengths: &[BigUint]) {
+    println!("0: {}", lengths[0]);
+    for (i, pair) in lengths.windows(2).enumerate() {
+        println!(
+            "{}: {} (x{:.9})",
+            i + 1,
+            pair[1],
+            growth_ratio(&pair[0], &pair[1])
+        );
+    }
+}
+
+fn save_series(lengths: &[BigUint], path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    let mut write_series = || {
+        writeln!(writer, "iteration,length,ratio")?;
+        writeln!(writer, "0,{},", lengths[0])?;
+        for (i, pair) in lengths.windows(2).enumerate() {
+            writeln!(
+                writer,
+                "{},{},{}",
+                i + 1,
+                pair[1],
+                growth_ratio(&pair[0], &pair[1])
+            )?;
+        }
+        writer.flush()
+    };
+    write_series().map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+// The elements seen so far, each with the elements it decays into after one iteration.
+#[derive(Default)]
+struct Elements {
+    ids: HashMap<Vec<u8>, usize>,
+    elements: Vec<Vec<u8>>,
+    decays: Vec<Option<Vec<usize>>>,
+}
+
+impl Elements {
+    fn id(&mut self, element: &[u8]) -> usize {
+        if let Some(&id) = self.ids.get(element) {
+            return id;
+        }
+        self.ids.insert(element.to_vec(), self.elements.len());
+        self.elements.push(element.to_vec());
+        self.decays.push(None);
+        self.elements.len() - 1
+    }
+
+    fn decay(&mut self, id: usize) -> &[usize] {
+        if self.decays[id].is_none() {
+            let mut next = Vec::new();
+            look_and_say(&self.elements[id], &mut next, &Variant::default());
+            let decay = split_elements(&next)
+                .into_iter()
+                .map(|element| self.id(element))
+                .collect();
+            self.decays[id] = Some(decay);
+        }
+        self.decays[id].as_ref().unwrap()
+    }
+}
+
+fn split_elements(digits: &[u8]) -> Vec<&[u8]> {
+    let mut elements = Vec::new();
+    let mut start = 0;
+    for i in 1..digits.len() {
+        if is_split(digits[i - 1], &digits[i..]) {
+            elements.push(&digits[start..i]);
+            start = i;
+        }
+    }
+    elements.push(&digits[start..]);
+    elements
+}
+
+// Conway's Splitting Theorem: a string at least two days old splits into L and R, which never
+// interact again, when L ends in 2 and R starts with one of the patterns below, or when L ends
+// in anything else and R starts with exactly two 2s followed by one of them.
+fn is_split(last: u8, rest: &[u8]) -> bool {
+    match (last, rest) {
+        (b'2', _) => starts_after_two(rest),
+        (_, [b'2', b'2', after @ ..]) => after.first() != Some(&b'2') && starts_after_two(after),
+        _ => false,
     }
-    let mut output = Vec::new();
-    for entry in out_queue {
-        output.extend(entry.1.to_string().chars());
-        output.push(entry.0);
+}
+
+fn starts_after_two(rest: &[u8]) -> bool {
+    let run = |start: usize| {
+        rest[start..]
+            .iter()
+            .take_while(|&&c| c == rest[start])
+            .count()
+    };
+    match rest {
+        // A single 1 followed by a single other digit.
+        [b'1', x, ..] if *x != b'1' => run(1) == 1,
+        // Exactly three 1s.
+        [b'1', b'1', b'1', ..] => run(0) == 3,
+        // A single 3 followed by anything but three of another digit.
+        [b'3', x, ..] if *x != b'3' => run(1) != 3,
+        // A single digit of 4 or more.
+        [n, ..] if n.is_ascii_digit() && *n >= b'4' => run(0) == 1,
+        _ => false,
+    }
+}
+
+// Writes the next term of the sequence into `output`, replacing what was there. Each run of
+// n digits becomes at most n + 1 digits, so reserving twice the input up front means the
+// buffer never grows part way through.
+fn look_and_say(input: &[u8], output: &mut Vec<u8>, variant: &Variant) {
+    output.clear();
+    output.reserve(2 * input.len());
+    match variant.grouping {
+        Grouping::Runs => {
+            for run in input.chunk_by(|a, b| a == b) {
+                variant.say(output, run.len(), run[0]);
+            }
+        }
+        Grouping::Totals => {
+            let mut totals = [0; 256];
+            for &digit in input {
+                totals[digit as usize] += 1;
+            }
+            for (digit, &count) in totals.iter().enumerate() {
+                if count > 0 {
+                    variant.say(output, count, digit as u8);
+                }
+            }
+        }
     }
-    output
 }
@@ -1,72 +1,688 @@
-use itertools::Itertools;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::env::args;
+use std::fmt::{self, Display, Formatter};
+
+// Parties up to this size are split across tables by exhaustive search.
+const EXACT_TABLE_SEARCH_LIMIT: usize = 12;
 
 fn main() {
     let pargs = Vec::from_iter(args());
-    if pargs.len() != 2 {
+    if pargs.len() < 2 {
         eprintln!("Please specify 'part1' or 'part2'");
         return;
     }
+    let options = match parse_options(&pargs[2..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
+        }
+    };
     let input = load_input();
-    match pargs[1].as_str() {
-        "part1" => {
-            println!("{}", part1(input))
+    let result = match pargs[1].as_str() {
+        "part1" => part1(input, &options),
+        "part2" => part2(input, &options),
+        _ => {
+            eprintln!("Invalid argument.");
+            return;
         }
-        "part2" => {
-            println!("{}", part2(input))
+    };
+    match result {
+        Ok(plan) => println!("{}", plan),
+        Err(message) => eprintln!("{}", message),
+    };
+}
+
+struct Options {
+    tables: Option<Vec<usize>>,
+    guest_changes: Vec<GuestChange>,
+}
+
+enum GuestChange {
+    // A new guest and how happy they are next to particular guests; anyone else is neutral.
+    Add {
+        name: String,
+        happiness: HashMap<String, i32>,
+    },
+    Remove(String),
+}
+
+fn parse_added_guest(guest: &str) -> Result<GuestChange, String> {
+    let (name, scores) = guest.split_once(':').unwrap_or((guest, ""));
+    let mut happiness = HashMap::new();
+    for score in scores.split(',').filter(|score| !score.is_empty()) {
+        let (neighbor, value) = score
+            .split_once('=')
+            .ok_or(format!("Invalid happiness score '{}'.", score))?;
+        let value = value
+            .parse::<i32>()
+            .map_err(|_| format!("Invalid happiness score '{}'.", score))?;
+        happiness.insert(neighbor.to_string(), value);
+    }
+    Ok(GuestChange::Add {
+        name: name.to_string(),
+        happiness,
+    })
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        tables: None,
+        guest_changes: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tables" => {
+                let capacities = args
+                    .next()
+                    .ok_or("Please specify table capacities, e.g. '--tables 4,4'")?;
+                let capacities = capacities
+                    .split(',')
+                    .map(|capacity| capacity.parse::<usize>())
+                    .collect::<Result<Vec<usize>, _>>()
+                    .map_err(|_| format!("Invalid table capacities '{}'.", capacities))?;
+                options.tables = Some(capacities);
+            }
+            "--add-guest" => {
+                let guest = args
+                    .next()
+                    .ok_or("Please specify a guest, e.g. '--add-guest Zoe' or '--add-guest Zoe:Alice=10,Bob=-5'")?;
+                options.guest_changes.push(parse_added_guest(guest)?);
+            }
+            "--remove-guest" => {
+                let name = args
+                    .next()
+                    .ok_or("Please specify a guest, e.g. '--remove-guest Alice'")?;
+                options
+                    .guest_changes
+                    .push(GuestChange::Remove(name.clone()));
+            }
+            _ => return Err(format!("Unknown option '{}'.", arg)),
         }
-        _ => {
-            eprintln!("Invalid argument.")
+    }
+    Ok(options)
+}
+
+// How happy a guest is to sit next to a neighbor, keyed by (guest, neighbor).
+type Preferences = HashMap<(String, String), i32>;
+
+enum SeatingConstraint {
+    Together(String, String),
+    Apart(String, String),
+}
+
+impl SeatingConstraint {
+    fn involves(&self, guest: &str) -> bool {
+        match self {
+            SeatingConstraint::Together(a, b) | SeatingConstraint::Apart(a, b) => {
+                a == guest || b == guest
+            }
+        }
+    }
+}
+
+// Hard seating constraints, indexed by guest ID.
+struct SeatingRules {
+    together: Vec<Vec<usize>>,
+    apart: Vec<Vec<bool>>,
+}
+
+impl SeatingRules {
+    fn build(guests: &[String], constraints: &[SeatingConstraint]) -> Result<Self, String> {
+        let guest_id = |name: &String| {
+            guests
+                .iter()
+                .position(|guest| guest == name)
+                .ok_or(format!("Unknown guest '{}' in seating constraint.", name))
+        };
+        let mut together = vec![Vec::new(); guests.len()];
+        let mut apart = vec![vec![false; guests.len()]; guests.len()];
+        for constraint in constraints {
+            match constraint {
+                SeatingConstraint::Together(a, b) => {
+                    let (a, b) = (guest_id(a)?, guest_id(b)?);
+                    together[a].push(b);
+                    together[b].push(a);
+                }
+                SeatingConstraint::Apart(a, b) => {
+                    let (a, b) = (guest_id(a)?, guest_id(b)?);
+                    apart[a][b] = true;
+                    apart[b][a] = true;
+                }
+            }
+        }
+        Ok(SeatingRules { together, apart })
+    }
+
+    // The rules for a table seating only `members`, or None if a member must sit with someone
+    // at another table.
+    fn for_table(&self, members: &[usize]) -> Option<SeatingRules> {
+        let table_ids: HashMap<usize, usize> = members
+            .iter()
+            .enumerate()
+            .map(|(table_id, &guest)| (guest, table_id))
+            .collect();
+        let mut together = Vec::new();
+        for &guest in members {
+            let partners = self.together[guest]
+                .iter()
+                .map(|partner| table_ids.get(partner).copied())
+                .collect::<Option<Vec<usize>>>()?;
+            together.push(partners);
         }
+        let apart = members
+            .iter()
+            .map(|&a| members.iter().map(|&b| self.apart[a][b]).collect())
+            .collect();
+        Some(SeatingRules { together, apart })
+    }
+
+    // Whether `next` may take the seat after `last` once the guests in `mask` are seated.
+    // Every guest `next` must sit with has to be `last`, still unseated, or guest 0 when
+    // `next` takes the final seat next to them.
+    fn can_seat_next(&self, mask: usize, last: usize, next: usize, full_mask: usize) -> bool {
+        let next_mask = mask | (1 << next);
+        if self.apart[last][next] || (next_mask == full_mask && self.apart[next][0]) {
+            return false;
+        }
+        self.together[next].iter().all(|&partner| {
+            partner == last
+                || mask & (1 << partner) == 0
+                || (partner == 0 && next_mask == full_mask)
+        })
+    }
+}
+
+struct SeatedGuest {
+    name: String,
+    left: String,
+    right: String,
+    happiness_from_left: i32,
+    happiness_from_right: i32,
+}
+
+impl SeatedGuest {
+    fn happiness(&self) -> i32 {
+        self.happiness_from_left + self.happiness_from_right
+    }
+}
+
+struct SeatingReport {
+    happiness: i32,
+    seats: Vec<SeatedGuest>,
+}
+
+impl SeatingReport {
+    fn least_happy_guest(&self) -> Option<&SeatedGuest> {
+        self.seats.iter().min_by_key(|seat| seat.happiness())
+    }
+}
+
+impl SeatingReport {
+    fn write_seats(&self, f: &mut Formatter) -> fmt::Result {
+        let order: Vec<&str> = self.seats.iter().map(|seat| seat.name.as_str()).collect();
+        writeln!(f, "Seating order: {}", order.join(", "))?;
+        for seat in &self.seats {
+            writeln!(
+                f,
+                "  {}: {:+} from {}, {:+} from {} ({:+})",
+                seat.name,
+                seat.happiness_from_left,
+                seat.left,
+                seat.happiness_from_right,
+                seat.right,
+                seat.happiness()
+            )?;
+        }
+        if let Some(seat) = self.least_happy_guest() {
+            write!(f, "Least happy: {} ({:+})", seat.name, seat.happiness())?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SeatingReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.happiness)?;
+        self.write_seats(f)
+    }
+}
+
+struct TableAssignment {
+    happiness: i32,
+    tables: Vec<(usize, SeatingReport)>,
+}
+
+impl Display for TableAssignment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.happiness)?;
+        for (table, (capacity, report)) in self.tables.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "Table {} ({} seats): {:+}",
+                table + 1,
+                capacity,
+                report.happiness
+            )?;
+            report.write_seats(f)?;
+        }
+        Ok(())
+    }
+}
+
+enum SeatingPlan {
+    SingleTable(SeatingReport),
+    MultiTable(TableAssignment),
+}
+
+impl Display for SeatingPlan {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SeatingPlan::SingleTable(report) => report.fmt(f),
+            SeatingPlan::MultiTable(assignment) => assignment.fmt(f),
+        }
+    }
+}
+
+fn part1(input: String, options: &Options) -> Result<SeatingPlan, String> {
+    let (mut guests, mut preferences, mut constraints) = parse_input(input);
+    apply_guest_changes(&mut guests, &mut preferences, &mut constraints, options)?;
+    plan_seating(guests, preferences, constraints, options)
+}
+
+fn part2(input: String, options: &Options) -> Result<SeatingPlan, String> {
+    let (mut guests, mut preferences, mut constraints) = parse_input(input);
+    add_guest(&mut guests, &mut preferences, "Me", &HashMap::new())?;
+    apply_guest_changes(&mut guests, &mut preferences, &mut constraints, options)?;
+    plan_seating(guests, preferences, constraints, options)
+}
+
+fn plan_seating(
+    guests: Vec<String>,
+    preferences: Preferences,
+    constraints: Vec<SeatingConstraint>,
+    options: &Options,
+) -> Result<SeatingPlan, String> {
+    match &options.tables {
+        Some(capacities) => find_optimal_tables(guests, preferences, constraints, capacities)
+            .map(SeatingPlan::MultiTable),
+        None => {
+            find_optimal_happiness(guests, preferences, constraints).map(SeatingPlan::SingleTable)
+        }
+    }
+}
+
+fn find_optimal_happiness(
+    guests: Vec<String>,
+    preferences: Preferences,
+    constraints: Vec<SeatingConstraint>,
+) -> Result<SeatingReport, String> {
+    let rules = SeatingRules::build(&guests, &constraints)?;
+    let happiness = build_happiness_matrix(&guests, &preferences);
+    let weights = build_pair_weights(&happiness);
+    let (total_happiness, arrangement) =
+        seat_guests(&weights, &rules).ok_or("No seating arrangement satisfies all constraints.")?;
+    Ok(build_report(
+        &guests,
+        &happiness,
+        total_happiness,
+        &arrangement,
+    ))
+}
+
+fn find_optimal_tables(
+    guests: Vec<String>,
+    preferences: Preferences,
+    constraints: Vec<SeatingConstraint>,
+    capacities: &[usize],
+) -> Result<TableAssignment, String> {
+    let num_seats: usize = capacities.iter().sum();
+    if num_seats < guests.len() {
+        return Err(format!(
+            "Not enough seats for {} guests at tables seating {}.",
+            guests.len(),
+            num_seats
+        ));
+    }
+    let rules = SeatingRules::build(&guests, &constraints)?;
+    let happiness = build_happiness_matrix(&guests, &preferences);
+    let weights = build_pair_weights(&happiness);
+    let mut planner = TablePlanner {
+        weights: &weights,
+        rules: &rules,
+        seatings: HashMap::new(),
     };
+    let tables = if guests.len() <= EXACT_TABLE_SEARCH_LIMIT {
+        assign_tables_exact(&mut planner, capacities)
+    } else {
+        assign_tables_local_search(&mut planner, capacities)
+    }
+    .ok_or("No seating arrangement satisfies all constraints.")?;
+
+    let mut total_happiness = 0;
+    let mut reports = Vec::new();
+    for (members, &capacity) in tables.iter().zip(capacities) {
+        let (table_happiness, arrangement) = planner.seat(members).unwrap();
+        total_happiness += table_happiness;
+        reports.push((
+            capacity,
+            build_report(&guests, &happiness, table_happiness, &arrangement),
+        ));
+    }
+    Ok(TableAssignment {
+        happiness: total_happiness,
+        tables: reports,
+    })
 }
 
-fn part1(input: String) -> i32 {
-    let (guests, preferences) = parse_input(input);
-    find_optimal_happiness(guests, preferences)
+// Seats guests at individual tables, remembering each table it has already solved.
+struct TablePlanner<'a> {
+    weights: &'a [Vec<i32>],
+    rules: &'a SeatingRules,
+    seatings: HashMap<Vec<usize>, Option<(i32, Vec<usize>)>>,
 }
 
-fn part2(input: String) -> i32 {
-    let (mut guests, mut preferences) = parse_input(input);
-    add_me(&mut guests, &mut preferences);
-    find_optimal_happiness(guests, preferences)
+impl TablePlanner<'_> {
+    fn num_guests(&self) -> usize {
+        self.weights.len()
+    }
+
+    // The best seating of a table with the given (sorted) members, in guest IDs.
+    fn seat(&mut self, members: &[usize]) -> Option<(i32, Vec<usize>)> {
+        if let Some(seating) = self.seatings.get(members) {
+            return seating.clone();
+        }
+        let weights: Vec<Vec<i32>> = members
+            .iter()
+            .map(|&a| members.iter().map(|&b| self.weights[a][b]).collect())
+            .collect();
+        let seating = self.rules.for_table(members).and_then(|rules| {
+            seat_guests(&weights, &rules).map(|(happiness, arrangement)| {
+                let arrangement = arrangement.into_iter().map(|seat| members[seat]).collect();
+                (happiness, arrangement)
+            })
+        });
+        self.seatings.insert(members.to_vec(), seating.clone());
+        seating
+    }
+
+    fn table_happiness(&mut self, members: &[usize]) -> Option<i32> {
+        self.seat(members).map(|(happiness, _)| happiness)
+    }
 }
 
-fn find_optimal_happiness(guests: Vec<String>, preferences: HashMap<(String, String), i32>) -> i32 {
-    let mut optimal_happiness = 0;
-    let num_guests = guests.len();
-    for seating_arrangement in guests.into_iter().permutations(num_guests) {
-        let happiness = find_arrangement_happiness(&preferences, seating_arrangement);
-        optimal_happiness = optimal_happiness.max(happiness);
+// Fills the tables one at a time, keeping the happiest way to seat every set of guests
+// at the tables filled so far.
+fn assign_tables_exact(
+    planner: &mut TablePlanner,
+    capacities: &[usize],
+) -> Option<Vec<Vec<usize>>> {
+    let num_guests = planner.num_guests();
+    let full_mask = (1usize << num_guests) - 1;
+    let members_of = |mask: usize| -> Vec<usize> {
+        (0..num_guests)
+            .filter(|guest| mask & (1 << guest) != 0)
+            .collect()
+    };
+    let mut best: Vec<Option<i32>> = vec![None; 1 << num_guests];
+    best[0] = Some(0);
+    let mut choices = Vec::new();
+    for &capacity in capacities {
+        let mut next_best: Vec<Option<i32>> = vec![None; 1 << num_guests];
+        let mut choice = vec![0; 1 << num_guests];
+        for mask in 0..=full_mask {
+            let Some(happiness) = best[mask] else {
+                continue;
+            };
+            let free = full_mask & !mask;
+            let mut table = free;
+            loop {
+                if table.count_ones() as usize <= capacity {
+                    if let Some(table_happiness) = planner.table_happiness(&members_of(table)) {
+                        let next_happiness = happiness + table_happiness;
+                        if next_best[mask | table].is_none_or(|b| next_happiness > b) {
+                            next_best[mask | table] = Some(next_happiness);
+                            choice[mask | table] = table;
+                        }
+                    }
+                }
+                if table == 0 {
+                    break;
+                }
+                table = (table - 1) & free;
+            }
+        }
+        best = next_best;
+        choices.push(choice);
+    }
+    best[full_mask]?;
+
+    let mut tables = Vec::new();
+    let mut mask = full_mask;
+    for choice in choices.iter().rev() {
+        tables.push(members_of(choice[mask]));
+        mask &= !choice[mask];
     }
-    optimal_happiness
+    tables.reverse();
+    Some(tables)
 }
 
-fn find_arrangement_happiness(
-    preferences: &HashMap<(String, String), i32>,
-    seating_arrangement: Vec<String>,
-) -> i32 {
-    let mut arrangement_happiness = 0;
+// Deals guests round the tables, then moves or swaps guests between tables while that
+// reduces the number of tables breaking a constraint or increases total happiness.
+fn assign_tables_local_search(
+    planner: &mut TablePlanner,
+    capacities: &[usize],
+) -> Option<Vec<Vec<usize>>> {
+    let mut tables: Vec<Vec<usize>> = vec![Vec::new(); capacities.len()];
+    let mut table = 0;
+    for guest in 0..planner.num_guests() {
+        while tables[table].len() == capacities[table] {
+            table = (table + 1) % capacities.len();
+        }
+        tables[table].push(guest);
+        table = (table + 1) % capacities.len();
+    }
 
-    for pair in seating_arrangement
-        .into_iter()
-        .circular_tuple_windows::<(String, String)>()
+    let mut score = score_tables(planner, &tables);
+    while let Some((next_tables, next_score)) =
+        find_better_tables(planner, capacities, &tables, score)
     {
-        arrangement_happiness += preferences.get(&(pair.0.clone(), pair.1.clone())).unwrap();
-        arrangement_happiness += preferences.get(&(pair.1, pair.0)).unwrap();
+        tables = next_tables;
+        score = next_score;
+    }
+    if score.0 < 0 {
+        return None;
+    }
+    Some(tables)
+}
+
+// Tables breaking a constraint (negated, so higher is better) and the happiness of the rest.
+type TableScore = (i32, i32);
+
+fn score_tables(planner: &mut TablePlanner, tables: &[Vec<usize>]) -> TableScore {
+    let mut score = (0, 0);
+    for members in tables {
+        match planner.table_happiness(members) {
+            Some(happiness) => score.1 += happiness,
+            None => score.0 -= 1,
+        }
+    }
+    score
+}
+
+fn find_better_tables(
+    planner: &mut TablePlanner,
+    capacities: &[usize],
+    tables: &[Vec<usize>],
+    score: TableScore,
+) -> Option<(Vec<Vec<usize>>, TableScore)> {
+    for a in 0..tables.len() {
+        for b in 0..tables.len() {
+            if a == b {
+                continue;
+            }
+            for i in 0..tables[a].len() {
+                let mut moves = Vec::new();
+                if tables[b].len() < capacities[b] {
+                    moves.push(None);
+                }
+                if a < b {
+                    moves.extend((0..tables[b].len()).map(Some));
+                }
+                for j in moves {
+                    let mut next_tables = tables.to_vec();
+                    let guest = next_tables[a].remove(i);
+                    if let Some(j) = j {
+                        let other = next_tables[b].remove(j);
+                        next_tables[a].push(other);
+                        next_tables[a].sort();
+                    }
+                    next_tables[b].push(guest);
+                    next_tables[b].sort();
+                    let next_score = score_tables(planner, &next_tables);
+                    if next_score > score {
+                        return Some((next_tables, next_score));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+fn build_report(
+    guests: &[String],
+    happiness: &[Vec<i32>],
+    total_happiness: i32,
+    arrangement: &[usize],
+) -> SeatingReport {
+    let num_guests = arrangement.len();
+    let seats = (0..num_guests)
+        .map(|seat| {
+            let guest = arrangement[seat];
+            let left = arrangement[(seat + num_guests - 1) % num_guests];
+            let right = arrangement[(seat + 1) % num_guests];
+            SeatedGuest {
+                name: guests[guest].clone(),
+                left: guests[left].clone(),
+                right: guests[right].clone(),
+                happiness_from_left: happiness[guest][left],
+                happiness_from_right: happiness[guest][right],
+            }
+        })
+        .collect();
+    SeatingReport {
+        happiness: total_happiness,
+        seats,
+    }
+}
+
+// How happy each guest is to sit next to each other guest, indexed by guest ID.
+fn build_happiness_matrix(guests: &[String], preferences: &Preferences) -> Vec<Vec<i32>> {
+    let guest_ids: HashMap<&str, usize> = guests
+        .iter()
+        .enumerate()
+        .map(|(id, guest)| (guest.as_str(), id))
+        .collect();
+    let mut happiness = vec![vec![0; guests.len()]; guests.len()];
+    for ((guest, neighbor), value) in preferences {
+        if let (Some(&a), Some(&b)) = (
+            guest_ids.get(guest.as_str()),
+            guest_ids.get(neighbor.as_str()),
+        ) {
+            happiness[a][b] = *value;
+        }
+    }
+    happiness
+}
+
+// Combined happiness for each pair of guests sitting next to each other.
+fn build_pair_weights(happiness: &[Vec<i32>]) -> Vec<Vec<i32>> {
+    let num_guests = happiness.len();
+    (0..num_guests)
+        .map(|a| {
+            (0..num_guests)
+                .map(|b| happiness[a][b] + happiness[b][a])
+                .collect()
+        })
+        .collect()
+}
+
+// Held-Karp over the table: guest 0 is fixed in the first seat so rotations of the same
+// arrangement are only considered once. `best[mask][last]` is the happiest run of seats
+// starting at guest 0, covering the guests in `mask` and ending with `last`.
+fn seat_guests(weights: &[Vec<i32>], rules: &SeatingRules) -> Option<(i32, Vec<usize>)> {
+    let num_guests = weights.len();
+    if num_guests < 2 {
+        return Some((0, (0..num_guests).collect()));
     }
+    let full_mask = (1usize << num_guests) - 1;
+    let mut best = vec![vec![i32::MIN; num_guests]; 1 << num_guests];
+    best[1][0] = 0;
+    for mask in (1..=full_mask).step_by(2) {
+        for last in 0..num_guests {
+            let happiness = best[mask][last];
+            if happiness == i32::MIN {
+                continue;
+            }
+            for next in 1..num_guests {
+                if mask & (1 << next) != 0 || !rules.can_seat_next(mask, last, next, full_mask) {
+                    continue;
+                }
+                let next_mask = mask | (1 << next);
+                let next_happiness = happiness + weights[last][next];
+                if next_happiness > best[next_mask][next] {
+                    best[next_mask][next] = next_happiness;
+                }
+            }
+        }
+    }
+    let (optimal_happiness, last) = (1..num_guests)
+        .filter(|&last| best[full_mask][last] != i32::MIN)
+        .map(|last| (best[full_mask][last] + weights[last][0], last))
+        .max()?;
 
-    arrangement_happiness
+    // Walk back through the table to recover the seating order.
+    let mut arrangement = vec![last];
+    let mut mask = full_mask;
+    let mut current = last;
+    while current != 0 {
+        let prev_mask = mask ^ (1 << current);
+        let prev = (0..num_guests)
+            .find(|&prev| {
+                best[prev_mask][prev] != i32::MIN
+                    && rules.can_seat_next(prev_mask, prev, current, full_mask)
+                    && best[prev_mask][prev] + weights[prev][current] == best[mask][current]
+            })
+            .unwrap();
+        arrangement.push(prev);
+        mask = prev_mask;
+        current = prev;
+    }
+    arrangement.reverse();
+    Some((optimal_happiness, arrangement))
 }
 
-fn parse_input(input: String) -> (Vec<String>, HashMap<(String, String), i32>) {
+fn parse_input(input: String) -> (Vec<String>, Preferences, Vec<SeatingConstraint>) {
     let mut preferences = HashMap::new();
     let mut guests_set = HashSet::new();
+    let mut constraints = Vec::new();
     let re = Regex::new(r"(\w+) would (gain|lose) (\d+) happiness units by sitting next to (\w+).")
         .unwrap();
+    let constraint_re = Regex::new(r"(\w+) must (not |)sit next to (\w+).").unwrap();
     for line in input.split("\n") {
+        if let Some(captures) = constraint_re.captures(line) {
+            let a = captures[1].to_string();
+            let b = captures[3].to_string();
+            constraints.push(match &captures[2] {
+                "not " => SeatingConstraint::Apart(a, b),
+                _ => SeatingConstraint::Together(a, b),
+            });
+            continue;
+        }
         let captures = re.captures(line).unwrap();
         let guest = captures[1].to_string();
         let neighbor = captures[4].to_string();
@@ -81,20 +697,63 @@ fn parse_input(input: String) -> (Vec<String>, HashMap<(String, String), i32>) {
         preferences.insert((guest, neighbor), happiness);
     }
     let guests = guests_set.into_iter().collect();
-    (guests, preferences)
+    (guests, preferences, constraints)
 }
 
-fn add_me(guests: &mut Vec<String>, preferences: &mut HashMap<(String, String), i32>) {
-    let me = "Me".to_string();
-    let mut new_pairings = Vec::new();
-    for guest in guests.into_iter() {
-        new_pairings.push((me.clone(), guest.clone()));
-        new_pairings.push((guest.clone(), me.clone()));
+fn apply_guest_changes(
+    guests: &mut Vec<String>,
+    preferences: &mut Preferences,
+    constraints: &mut Vec<SeatingConstraint>,
+    options: &Options,
+) -> Result<(), String> {
+    for change in &options.guest_changes {
+        match change {
+            GuestChange::Add { name, happiness } => {
+                add_guest(guests, preferences, name, happiness)?
+            }
+            GuestChange::Remove(name) => remove_guest(guests, preferences, constraints, name)?,
+        }
+    }
+    Ok(())
+}
+
+fn add_guest(
+    guests: &mut Vec<String>,
+    preferences: &mut Preferences,
+    name: &str,
+    happiness: &HashMap<String, i32>,
+) -> Result<(), String> {
+    if guests.iter().any(|guest| guest == name) {
+        return Err(format!("Guest '{}' is already invited.", name));
     }
-    for pairing in new_pairings {
-        preferences.insert(pairing, 0);
+    if let Some(neighbor) = happiness.keys().find(|neighbor| !guests.contains(neighbor)) {
+        return Err(format!(
+            "Unknown guest '{}' in scores for '{}'.",
+            neighbor, name
+        ));
+    }
+    for guest in guests.iter() {
+        let value = happiness.get(guest).copied().unwrap_or(0);
+        preferences.insert((name.to_string(), guest.clone()), value);
+        preferences.insert((guest.clone(), name.to_string()), 0);
+    }
+    guests.push(name.to_string());
+    Ok(())
+}
+
+fn remove_guest(
+    guests: &mut Vec<String>,
+    preferences: &mut Preferences,
+    constraints: &mut Vec<SeatingConstraint>,
+    name: &str,
+) -> Result<(), String> {
+    if !guests.iter().any(|guest| guest == name) {
+        return Err(format!("Unknown guest '{}'.", name));
     }
-    guests.push(me.clone());
+    guests.retain(|guest| guest != name);
+    preferences.retain(|(guest, neighbor), _| guest != name && neighbor != name);
+    constraints.retain(|constraint| !constraint.involves(name));
+    Ok(())
 }
 
 fn load_input() -> String {
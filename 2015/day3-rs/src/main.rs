@@ -1,58 +1,592 @@
-use std::{collections::HashMap, env::args};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env::args,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+// Houses are (x, y, z) with north as +y and up as +z. Hex grids use axial coordinates, with
+// northeast as +x and north as +y.
+type House = (i32, i32, i32);
+type Route = Vec<House>;
+type VisitMap = HashMap<House, u32>;
+
+// The number of houses listed as most visited in the report.
+const MOST_VISITED_COUNT: usize = 10;
+
+// Size in pixels of a house on the route map, and of the margin around it.
+const ROUTE_CELL_SIZE: u32 = 10;
+const ROUTE_MARGIN: u32 = 20;
 
 fn main() {
     let pargs = Vec::from_iter(args());
-    if pargs.len() != 2 {
-        eprintln!("Please specify 'part1' or 'part2'");
+    if pargs.len() < 2 {
+        eprintln!("Please specify 'part1', 'part2' or 'compare'");
         return;
     }
+    let options = match parse_options(&pargs[2..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
+        }
+    };
     let raw_input = load_input();
     let input = raw_input.trim();
 
-    match pargs[1].as_str() {
-        "part1" => {
-            println!("{}", part1(input))
-        }
-        "part2" => {
-            println!("{}", part2(input))
+    if pargs[1] == "compare" {
+        match compare_dispatch(input, &options) {
+            Ok(report) => print!("{}", report),
+            Err(message) => eprintln!("{}", message),
         }
+        return;
+    }
+    let routes = match pargs[1].as_str() {
+        "part1" => part1(input, &options),
+        "part2" => part2(input, &options),
         _ => {
-            eprintln!("Invalid argument.")
+            eprintln!("Invalid argument.");
+            return;
+        }
+    };
+    let routes = match routes {
+        Ok(routes) => routes,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
         }
     };
+    let visited = count_visits(&routes);
+    println!("{}", visited.len());
+    if options.stats {
+        print!("{}", VisitReport::new(&visited));
+    }
+    if let Some(path) = &options.heatmap {
+        if let Err(message) = save_heatmap(&visited, path) {
+            eprintln!("{}", message);
+        }
+    }
+    if let Some(path) = &options.routes {
+        if let Err(message) = save_routes(&routes, path) {
+            eprintln!("{}", message);
+        }
+    }
+}
+
+// How moves are shared out between the visitors.
+enum Dispatch {
+    // Each visitor takes the next move in turn.
+    RoundRobin,
+    // The moves are split into one contiguous block per visitor.
+    Blocks,
+    // Visitor indices repeated over the moves, e.g. [0, 0, 1] gives visitor 0 two moves for
+    // each of visitor 1's.
+    Pattern(Vec<usize>),
+}
+
+impl Dispatch {
+    fn visitor(&self, move_index: usize, num_moves: usize, num_visitors: usize) -> usize {
+        match self {
+            Dispatch::RoundRobin => move_index % num_visitors,
+            Dispatch::Blocks => move_index * num_visitors / num_moves,
+            Dispatch::Pattern(pattern) => pattern[move_index % pattern.len()],
+        }
+    }
 }
 
-fn part1(input: &str) -> u32 {
-    visit_houses(1, input)
+impl Display for Dispatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Dispatch::RoundRobin => write!(f, "round-robin"),
+            Dispatch::Blocks => write!(f, "blocks"),
+            Dispatch::Pattern(pattern) => {
+                for visitor in pattern {
+                    write!(f, "{}", visitor + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
-fn part2(input: &str) -> u32 {
-    visit_houses(2, input)
+// Parses 'round-robin', 'blocks' or a pattern of visitor numbers such as '1121'.
+fn parse_dispatch(dispatch: &str) -> Option<Dispatch> {
+    match dispatch {
+        "round-robin" => Some(Dispatch::RoundRobin),
+        "blocks" => Some(Dispatch::Blocks),
+        "" => None,
+        _ => dispatch
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(visitor) if visitor > 0 => Some(visitor as usize - 1),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .map(Dispatch::Pattern),
+    }
 }
 
-fn visit_houses(num_visitors: usize, input: &str) -> u32 {
-    let mut visitors = vec![(0, 0); num_visitors as usize];
-    let mut visiterator = (0..num_visitors).cycle();
-    let mut visited = HashMap::from([((0i32, 0i32), 1u32)]);
-    for c in input.chars() {
-        let visitor = visiterator.next().unwrap();
-        let (mut x, mut y) = visitors[visitor as usize];
-        match c {
-            '^' => y += 1,
-            'v' => y -= 1,
-            '>' => x += 1,
-            '<' => x -= 1,
-            _ => {}
+// The directions that moves can go in, and how they are written.
+#[derive(Clone, Copy)]
+enum Movement {
+    // '^', 'v', '>' and '<'.
+    Grid,
+    // As for the grid, with the corners of a numeric keypad for diagonals: '7' for northwest,
+    // '9' for northeast, '1' for southwest and '3' for southeast.
+    Diagonal,
+    // Comma separated 'n', 'ne', 'se', 's', 'sw' and 'nw'.
+    Hex,
+    // As for the grid, with 'u' and 'd' for up and down.
+    Space,
+}
+
+impl Movement {
+    // Returns the change in position for each move. Unknown moves stay in place unless strict.
+    fn parse_moves(&self, input: &str, strict: bool) -> Result<Vec<House>, String> {
+        let tokens: Vec<&str> = match self {
+            Movement::Hex => input.split(',').map(|token| token.trim()).collect(),
+            _ => input.matches(|_| true).collect(),
         };
-        let new_location = (x, y);
-        let visits = match visited.get(&new_location) {
-            Some(v) => v + 1,
-            None => 1,
+        tokens
+            .iter()
+            .enumerate()
+            .map(|(i, &token)| match (self.step(token), strict) {
+                (Some(step), _) => Ok(step),
+                (None, false) => Ok((0, 0, 0)),
+                (None, true) => Err(format!("Unknown move '{}' at move {}.", token, i + 1)),
+            })
+            .collect()
+    }
+
+    fn step(&self, token: &str) -> Option<House> {
+        let grid_step = match token {
+            "^" => Some((0, 1, 0)),
+            "v" => Some((0, -1, 0)),
+            ">" => Some((1, 0, 0)),
+            "<" => Some((-1, 0, 0)),
+            _ => None,
         };
-        visited.insert(new_location, visits);
+        match (self, token) {
+            (Movement::Grid, _) => grid_step,
+            (Movement::Diagonal, "7") => Some((-1, 1, 0)),
+            (Movement::Diagonal, "9") => Some((1, 1, 0)),
+            (Movement::Diagonal, "1") => Some((-1, -1, 0)),
+            (Movement::Diagonal, "3") => Some((1, -1, 0)),
+            (Movement::Diagonal, _) => grid_step,
+            (Movement::Hex, "n") => Some((0, 1, 0)),
+            (Movement::Hex, "ne") => Some((1, 0, 0)),
+            (Movement::Hex, "se") => Some((1, -1, 0)),
+            (Movement::Hex, "s") => Some((0, -1, 0)),
+            (Movement::Hex, "sw") => Some((-1, 0, 0)),
+            (Movement::Hex, "nw") => Some((-1, 1, 0)),
+            (Movement::Hex, _) => None,
+            (Movement::Space, "u") => Some((0, 0, 1)),
+            (Movement::Space, "d") => Some((0, 0, -1)),
+            (Movement::Space, _) => grid_step,
+        }
+    }
+}
+
+struct Options {
+    movement: Movement,
+    strict: bool,
+    visitors: Option<usize>,
+    dispatch: Dispatch,
+    stats: bool,
+    heatmap: Option<String>,
+    routes: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        movement: Movement::Grid,
+        strict: false,
+        visitors: None,
+        dispatch: Dispatch::RoundRobin,
+        stats: false,
+        heatmap: None,
+        routes: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--visitors" => {
+                let visitors = args
+                    .next()
+                    .and_then(|visitors| visitors.parse().ok())
+                    .filter(|&visitors| visitors > 0)
+                    .ok_or("Please specify a number of visitors, e.g. '--visitors 3'")?;
+                options.visitors = Some(visitors);
+            }
+            "--dispatch" => {
+                options.dispatch = args
+                    .next()
+                    .and_then(|dispatch| parse_dispatch(dispatch))
+                    .ok_or(
+                        "Please specify 'round-robin', 'blocks' or a pattern of visitor \
+                         numbers, e.g. '--dispatch 1121'",
+                    )?;
+            }
+            "--moves" => {
+                options.movement = match args.next().map(|movement| movement.as_str()) {
+                    Some("grid") => Movement::Grid,
+                    Some("diagonal") => Movement::Diagonal,
+                    Some("hex") => Movement::Hex,
+                    Some("3d") => Movement::Space,
+                    _ => return Err("Please specify 'grid', 'diagonal', 'hex' or '3d'".to_string()),
+                };
+            }
+            "--strict" => options.strict = true,
+            "--stats" => options.stats = true,
+            "--heatmap" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify an image file, e.g. '--heatmap houses.svg'")?;
+                options.heatmap = Some(path.clone());
+            }
+            "--routes" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify an SVG file, e.g. '--routes routes.svg'")?;
+                options.routes = Some(path.clone());
+            }
+            _ => return Err(format!("Unknown option '{}'.", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn part1(input: &str, options: &Options) -> Result<Vec<Route>, String> {
+    let moves = options.movement.parse_moves(input, options.strict)?;
+    visit_houses(options.visitors.unwrap_or(1), &options.dispatch, &moves)
+}
+
+fn part2(input: &str, options: &Options) -> Result<Vec<Route>, String> {
+    let moves = options.movement.parse_moves(input, options.strict)?;
+    visit_houses(options.visitors.unwrap_or(2), &options.dispatch, &moves)
+}
+
+// Counts the houses covered by each dispatch strategy, including the one given with
+// '--dispatch' if it is a pattern.
+fn compare_dispatch(input: &str, options: &Options) -> Result<String, String> {
+    let num_visitors = options.visitors.unwrap_or(2);
+    let moves = options.movement.parse_moves(input, options.strict)?;
+    let mut strategies = vec![Dispatch::RoundRobin, Dispatch::Blocks];
+    if let Dispatch::Pattern(pattern) = &options.dispatch {
+        strategies.push(Dispatch::Pattern(pattern.clone()));
+    }
+    let mut report = String::new();
+    for dispatch in strategies {
+        let routes = visit_houses(num_visitors, &dispatch, &moves)?;
+        report += &format!("{}: {}\n", dispatch, count_visits(&routes).len());
+    }
+    Ok(report)
+}
+
+// Returns the houses each visitor passed through in order, starting from the origin.
+fn visit_houses(
+    num_visitors: usize,
+    dispatch: &Dispatch,
+    moves: &[House],
+) -> Result<Vec<Route>, String> {
+    if let Dispatch::Pattern(pattern) = dispatch {
+        if pattern.iter().any(|&visitor| visitor >= num_visitors) {
+            return Err(format!(
+                "Dispatch pattern '{}' needs more than {} visitors.",
+                dispatch, num_visitors
+            ));
+        }
+    }
+    let mut visitors = vec![(0, 0, 0); num_visitors];
+    let mut routes = vec![vec![(0, 0, 0)]; num_visitors];
+    for (i, &(dx, dy, dz)) in moves.iter().enumerate() {
+        let visitor = dispatch.visitor(i, moves.len(), num_visitors);
+        let (x, y, z) = visitors[visitor];
+        let new_location = (x + dx, y + dy, z + dz);
+        routes[visitor].push(new_location);
         visitors[visitor] = new_location;
     }
-    visited.len() as u32
+    Ok(routes)
+}
+
+// Returns the number of times each house was visited, including the starting house.
+fn count_visits(routes: &[Route]) -> VisitMap {
+    let mut visited = HashMap::from([((0, 0, 0), 1)]);
+    for route in routes {
+        for &house in &route[1..] {
+            *visited.entry(house).or_insert(0) += 1;
+        }
+    }
+    visited
+}
+
+#[derive(Clone, Copy)]
+// The extent of a set of houses seen from above.
+struct Bounds {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl Bounds {
+    fn new<'a>(houses: impl IntoIterator<Item = &'a House>) -> Self {
+        let mut bounds = Bounds {
+            min: (0, 0),
+            max: (0, 0),
+        };
+        for &(x, y, _) in houses {
+            bounds.min = (bounds.min.0.min(x), bounds.min.1.min(y));
+            bounds.max = (bounds.max.0.max(x), bounds.max.1.max(y));
+        }
+        bounds
+    }
+
+    fn width(&self) -> u32 {
+        (self.max.0 - self.min.0) as u32 + 1
+    }
+
+    fn height(&self) -> u32 {
+        (self.max.1 - self.min.1) as u32 + 1
+    }
+
+    // Image coordinates of a house, with north at the top.
+    fn pixel(&self, (x, y, _): House) -> (u32, u32) {
+        ((x - self.min.0) as u32, (self.max.1 - y) as u32)
+    }
+}
+
+struct VisitReport {
+    bounds: Bounds,
+    // Lowest and highest level reached, for routes that leave the ground.
+    levels: Option<(i32, i32)>,
+    most_visited: Vec<(House, u32)>,
+    // Number of houses by the number of times they were visited.
+    histogram: BTreeMap<u32, u32>,
+}
+
+impl VisitReport {
+    fn new(visited: &VisitMap) -> Self {
+        let mut most_visited =
+            Vec::from_iter(visited.iter().map(|(&house, &visits)| (house, visits)));
+        most_visited.sort_by(|(house1, visits1), (house2, visits2)| {
+            visits2.cmp(visits1).then(house1.cmp(house2))
+        });
+        most_visited.truncate(MOST_VISITED_COUNT);
+        let mut histogram = BTreeMap::new();
+        for &visits in visited.values() {
+            *histogram.entry(visits).or_insert(0) += 1;
+        }
+        let levels = visited.keys().map(|house| house.2);
+        let levels = match (levels.clone().min(), levels.max()) {
+            (Some(0), Some(0)) | (None, _) | (_, None) => None,
+            (Some(low), Some(high)) => Some((low, high)),
+        };
+        VisitReport {
+            bounds: Bounds::new(visited.keys()),
+            levels,
+            most_visited,
+            histogram,
+        }
+    }
+}
+
+impl Display for VisitReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Bounds { min, max } = self.bounds;
+        writeln!(
+            f,
+            "Bounds: ({}, {}) to ({}, {}), {}x{}",
+            min.0,
+            min.1,
+            max.0,
+            max.1,
+            self.bounds.width(),
+            self.bounds.height()
+        )?;
+        if let Some((low, high)) = self.levels {
+            writeln!(f, "Levels: {} to {}", low, high)?;
+        }
+        writeln!(f, "Most visited:")?;
+        for &((x, y, z), visits) in &self.most_visited {
+            match self.levels {
+                Some(_) => writeln!(f, "  ({}, {}, {}): {}", x, y, z, visits)?,
+                None => writeln!(f, "  ({}, {}): {}", x, y, visits)?,
+            }
+        }
+        writeln!(f, "Houses by visits:")?;
+        for (visits, houses) in &self.histogram {
+            writeln!(f, "  {}: {}", visits, houses)?;
+        }
+        Ok(())
+    }
+}
+
+// Colour for a house on a black to red to yellow to white scale. Visits are scaled
+// logarithmically, as the starting house is usually visited far more often than the rest.
+fn heat_colour(visits: u32, max_visits: u32) -> [u8; 3] {
+    let t = (visits as f64).ln_1p() / (max_visits as f64).ln_1p();
+    let channel = |t: f64| (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [
+        channel(3.0 * t),
+        channel(3.0 * t - 1.0),
+        channel(3.0 * t - 2.0),
+    ]
+}
+
+// Writes one pixel per house, as an SVG if the path ends in ".svg" and a PPM image otherwise.
+// Houses on different levels are combined into the one pixel.
+fn save_heatmap(visited: &VisitMap, path: &str) -> Result<(), String> {
+    let mut projected = VisitMap::new();
+    for (&(x, y, _), &visits) in visited {
+        *projected.entry((x, y, 0)).or_insert(0) += visits;
+    }
+    let visited = &projected;
+    let bounds = Bounds::new(visited.keys());
+    let max_visits = visited.values().copied().max().unwrap_or(1);
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    let mut write_heatmap = || {
+        if path.ends_with(".svg") {
+            writeln!(
+                writer,
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" \
+                 shape-rendering=\"crispEdges\">",
+                bounds.width(),
+                bounds.height()
+            )?;
+            writeln!(
+                writer,
+                "<rect width=\"100%\" height=\"100%\" fill=\"black\"/>"
+            )?;
+            for (&house, &visits) in visited {
+                let (px, py) = bounds.pixel(house);
+                let [r, g, b] = heat_colour(visits, max_visits);
+                writeln!(
+                    writer,
+                    "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"#{:02x}{:02x}{:02x}\"/>",
+                    px, py, r, g, b
+                )?;
+            }
+            writeln!(writer, "</svg>")?;
+        } else {
+            let (width, height) = (bounds.width(), bounds.height());
+            let mut pixels = vec![0; width as usize * height as usize * 3];
+            for (&house, &visits) in visited {
+                let (px, py) = bounds.pixel(house);
+                let i = (py as usize * width as usize + px as usize) * 3;
+                pixels[i..i + 3].copy_from_slice(&heat_colour(visits, max_visits));
+            }
+            write!(writer, "P6\n{} {}\n255\n", width, height)?;
+            writer.write_all(&pixels)?;
+        }
+        writer.flush()
+    };
+    write_heatmap().map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+// Evenly spaced hues around the colour wheel, one per visitor.
+fn route_colour(visitor: usize, num_visitors: usize) -> [u8; 3] {
+    let hue = visitor as f64 * 6.0 / num_visitors as f64;
+    let channel = |offset: f64| {
+        let distance = ((hue - offset).rem_euclid(6.0) - 3.0).abs();
+        ((distance - 1.0).clamp(0.0, 1.0) * 200.0).round() as u8
+    };
+    [channel(0.0), channel(2.0), channel(4.0)]
+}
+
+// Draws each visitor's route as a line over a grid of the houses, with the origin circled and
+// a legend underneath.
+fn save_routes(routes: &[Route], path: &str) -> Result<(), String> {
+    let bounds = Bounds::new(routes.iter().flatten());
+    let centre = |house: House| {
+        let (px, py) = bounds.pixel(house);
+        (
+            ROUTE_MARGIN + px * ROUTE_CELL_SIZE + ROUTE_CELL_SIZE / 2,
+            ROUTE_MARGIN + py * ROUTE_CELL_SIZE + ROUTE_CELL_SIZE / 2,
+        )
+    };
+    let grid_width = bounds.width() * ROUTE_CELL_SIZE;
+    let grid_height = bounds.height() * ROUTE_CELL_SIZE;
+    let legend_height = routes.len() as u32 * ROUTE_MARGIN;
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    let mut write_routes = || {
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            grid_width + 2 * ROUTE_MARGIN,
+            grid_height + 2 * ROUTE_MARGIN + legend_height
+        )?;
+        writeln!(
+            writer,
+            "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>"
+        )?;
+        writeln!(writer, "<g stroke=\"#e0e0e0\" stroke-width=\"0.5\">")?;
+        for column in 0..=bounds.width() {
+            let x = ROUTE_MARGIN + column * ROUTE_CELL_SIZE;
+            writeln!(
+                writer,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                x,
+                ROUTE_MARGIN,
+                x,
+                ROUTE_MARGIN + grid_height
+            )?;
+        }
+        for row in 0..=bounds.height() {
+            let y = ROUTE_MARGIN + row * ROUTE_CELL_SIZE;
+            writeln!(
+                writer,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                ROUTE_MARGIN,
+                y,
+                ROUTE_MARGIN + grid_width,
+                y
+            )?;
+        }
+        writeln!(writer, "</g>")?;
+        for (visitor, route) in routes.iter().enumerate() {
+            let [r, g, b] = route_colour(visitor, routes.len());
+            let points = Vec::from_iter(route.iter().map(|&house| {
+                let (x, y) = centre(house);
+                format!("{},{}", x, y)
+            }));
+            writeln!(
+                writer,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"#{:02x}{:02x}{:02x}\" \
+                 stroke-width=\"2\" stroke-opacity=\"0.7\" stroke-linejoin=\"round\"/>",
+                points.join(" "),
+                r,
+                g,
+                b
+            )?;
+            let y = grid_height + 2 * ROUTE_MARGIN + visitor as u32 * ROUTE_MARGIN;
+            writeln!(
+                writer,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#{:02x}{:02x}{:02x}\"/>",
+                ROUTE_MARGIN, y, ROUTE_CELL_SIZE, ROUTE_CELL_SIZE, r, g, b
+            )?;
+            writeln!(
+                writer,
+                "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\
+                 Visitor {}: {} moves</text>",
+                ROUTE_MARGIN + 2 * ROUTE_CELL_SIZE,
+                y + ROUTE_CELL_SIZE,
+                visitor + 1,
+                route.len() - 1
+            )?;
+        }
+        let (x, y) = centre((0, 0, 0));
+        writeln!(
+            writer,
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"black\" \
+             stroke-width=\"2\"/>",
+            x, y, ROUTE_CELL_SIZE
+        )?;
+        writeln!(writer, "</svg>")?;
+        writer.flush()
+    };
+    write_routes().map_err(|e| format!("Failed to write {}: {}", path, e))
 }
 
 fn load_input() -> String {
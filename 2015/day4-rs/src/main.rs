@@ -1,47 +1,493 @@
-use hex;
+use md5::digest::Output;
 use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::collections::BTreeSet;
 use std::env::args;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SEARCH_LIMIT: u32 = 2_000_000_000;
+
+// Candidates each worker thread claims at a time.
+const CHUNK_SIZE: u32 = 10_000;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HashAlgorithm::Md5 => write!(f, "md5"),
+            HashAlgorithm::Sha1 => write!(f, "sha1"),
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+enum Difficulty {
+    ZeroBits(usize),
+    // Leading hex digits of the digest, one nibble per entry.
+    HexPrefix(Vec<u8>),
+}
+
+impl Difficulty {
+    fn is_met(&self, digest: &[u8]) -> bool {
+        match self {
+            Difficulty::ZeroBits(bits) => has_leading_zero_bits(digest, *bits),
+            Difficulty::HexPrefix(nibbles) => has_hex_prefix(digest, nibbles),
+        }
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Difficulty::ZeroBits(bits) => write!(f, "bits={}", bits),
+            Difficulty::HexPrefix(nibbles) => {
+                write!(f, "prefix=")?;
+                nibbles
+                    .iter()
+                    .try_for_each(|nibble| write!(f, "{:x}", nibble))
+            }
+        }
+    }
+}
+
+// Where an interrupted search picks up from. The file records which search it belongs to,
+// so a checkpoint left by a different input, hash or difficulty is ignored.
+struct Checkpoint {
+    path: String,
+    search: String,
+}
+
+impl Checkpoint {
+    fn load(&self) -> u32 {
+        let Ok(contents) = fs::read_to_string(&self.path) else {
+            return 0;
+        };
+        match contents.split_once('\n') {
+            Some((search, nonce)) if search == self.search => {
+                let nonce = nonce.trim().parse().unwrap_or(0);
+                eprintln!("Resuming from nonce {}", nonce);
+                nonce
+            }
+            _ => {
+                eprintln!(
+                    "Ignoring checkpoint for a different search in {}",
+                    self.path
+                );
+                0
+            }
+        }
+    }
+
+    fn save(&self, nonce: u32) {
+        let contents = format!("{}\n{}\n", self.search, nonce);
+        if let Err(error) = fs::write(&self.path, contents) {
+            eprintln!("Failed to write checkpoint {}: {}", self.path, error);
+        }
+    }
+
+    fn clear(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Chunks searched without a match. Everything below `frontier` has been searched, which is
+// where a resumed search starts from.
+struct CompletedChunks {
+    frontier: u32,
+    pending: BTreeSet<u32>,
+}
+
+impl CompletedChunks {
+    fn complete(&mut self, chunk: u32) {
+        self.pending.insert(chunk);
+        while self.pending.remove(&self.frontier) {
+            self.frontier += 1;
+        }
+    }
+}
+
+struct Options {
+    threads: usize,
+    hash: HashAlgorithm,
+    difficulty: Option<Difficulty>,
+    input: String,
+    checkpoint: Option<String>,
+    range: Range<u32>,
+    limit: Option<usize>,
+    count: bool,
+}
 
 fn main() {
     let pargs = Vec::from_iter(args());
-    if pargs.len() != 2 {
-        eprintln!("Please specify 'part1' or 'part2'");
+    if pargs.len() < 2 {
+        eprintln!("Please specify 'part1', 'part2', 'mine', 'all' or 'bench'");
         return;
     }
-    let input = "iwrupvqb";
-    match pargs[1].as_str() {
-        "part1" => {
-            println!("{}", part1(input))
+    let options = match parse_options(&pargs[2..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
         }
-        "part2" => {
-            println!("{}", part2(input))
+    };
+    match (pargs[1].as_str(), &options.difficulty) {
+        ("part1", _) => print_nonce(part1(&options)),
+        ("part2", _) => print_nonce(part2(&options)),
+        ("mine", Some(difficulty)) => print_nonce(search(difficulty, &options)),
+        ("all", Some(difficulty)) => match options.hash {
+            HashAlgorithm::Md5 => list_nonces::<Md5>(difficulty, &options),
+            HashAlgorithm::Sha1 => list_nonces::<Sha1>(difficulty, &options),
+            HashAlgorithm::Sha256 => list_nonces::<Sha256>(difficulty, &options),
+        },
+        ("mine" | "all", None) => {
+            eprintln!("Please specify a difficulty, e.g. '--bits 20' or '--prefix 00000'")
         }
+        ("bench", _) => bench(&options.input, options.threads),
         _ => {
             eprintln!("Invalid argument.")
         }
     };
 }
 
-fn part1(input: &str) -> u32 {
-    find_zeroes(input, 5)
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        hash: HashAlgorithm::Md5,
+        difficulty: None,
+        input: "iwrupvqb".to_string(),
+        checkpoint: None,
+        range: 0..SEARCH_LIMIT,
+        limit: None,
+        count: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => {
+                options.threads = args
+                    .next()
+                    .and_then(|threads| threads.parse().ok())
+                    .filter(|&threads| threads > 0)
+                    .ok_or("Please specify a number of threads, e.g. '--threads 4'")?;
+            }
+            "--hash" => {
+                options.hash = match args.next().map(|hash| hash.as_str()) {
+                    Some("md5") => HashAlgorithm::Md5,
+                    Some("sha1") => HashAlgorithm::Sha1,
+                    Some("sha256") => HashAlgorithm::Sha256,
+                    _ => return Err("Please specify 'md5', 'sha1' or 'sha256'".to_string()),
+                };
+            }
+            "--bits" => {
+                let bits = args
+                    .next()
+                    .and_then(|bits| bits.parse().ok())
+                    .ok_or("Please specify a number of bits, e.g. '--bits 20'")?;
+                options.difficulty = Some(Difficulty::ZeroBits(bits));
+            }
+            "--prefix" => {
+                let prefix = args
+                    .next()
+                    .ok_or("Please specify a hex prefix, e.g. '--prefix 00000'")?;
+                let nibbles = prefix
+                    .chars()
+                    .map(|c| c.to_digit(16).map(|nibble| nibble as u8))
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or(format!("Invalid hex prefix '{}'.", prefix))?;
+                options.difficulty = Some(Difficulty::HexPrefix(nibbles));
+            }
+            "--input" => {
+                options.input = args
+                    .next()
+                    .ok_or("Please specify a secret key, e.g. '--input abcdef'")?
+                    .clone();
+            }
+            "--checkpoint" => {
+                let path = args.next().ok_or(
+                    "Please specify a checkpoint file, e.g. '--checkpoint day4.checkpoint'",
+                )?;
+                options.checkpoint = Some(path.clone());
+            }
+            "--range" => {
+                options.range = args
+                    .next()
+                    .and_then(|range| range.split_once(".."))
+                    .and_then(|(start, end)| Some(start.parse().ok()?..end.parse().ok()?))
+                    .ok_or("Please specify a range of nonces, e.g. '--range 0..1000000'")?;
+            }
+            "--limit" => {
+                let limit = args
+                    .next()
+                    .and_then(|limit| limit.parse().ok())
+                    .ok_or("Please specify a number of nonces, e.g. '--limit 10'")?;
+                options.limit = Some(limit);
+            }
+            "--count" => options.count = true,
+            _ => return Err(format!("Unknown option '{}'.", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn print_nonce(nonce: Option<u32>) {
+    match nonce {
+        Some(nonce) => println!("{}", nonce),
+        None => eprintln!("No qualifying nonce below {}.", SEARCH_LIMIT),
+    }
+}
+
+// Prints every qualifying nonce in the range with its digest, or just how many there are.
+fn list_nonces<D: Digest + Clone>(difficulty: &Difficulty, options: &Options) {
+    let matches = find_all_nonces::<D>(&options.input, difficulty, options.range.clone())
+        .take(options.limit.unwrap_or(usize::MAX));
+    if options.count {
+        println!("{}", matches.count());
+    } else {
+        for (nonce, digest) in matches {
+            let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+            println!("{} {}", nonce, hex);
+        }
+    }
+}
+
+fn part1(options: &Options) -> Option<u32> {
+    search(&Difficulty::ZeroBits(5 * 4), options)
 }
 
-fn part2(input: &str) -> u32 {
-    find_zeroes(input, 6)
+fn part2(options: &Options) -> Option<u32> {
+    search(&Difficulty::ZeroBits(6 * 4), options)
 }
 
-fn find_zeroes(input: &str, zeroes: usize) -> u32 {
-    let target = "0".repeat(zeroes);
-    for i in 0..2_000000_000 {
-        let candidate_str = i.to_string();
-        let test_str = input.to_owned() + &candidate_str;
-        let mut hasher = Md5::new();
-        hasher.update(test_str.as_bytes());
-        let result = hasher.finalize();
-        let hexval = hex::encode(&result);
-        if hexval.starts_with(&target) {
-            return i;
+fn search(difficulty: &Difficulty, options: &Options) -> Option<u32> {
+    let (input, threads) = (&options.input, options.threads);
+    let checkpoint = options.checkpoint.as_ref().map(|path| Checkpoint {
+        path: path.clone(),
+        search: format!("{} {} {}", options.hash, input, difficulty),
+    });
+    let checkpoint = checkpoint.as_ref();
+    match options.hash {
+        HashAlgorithm::Md5 => find_nonce_parallel::<Md5>(input, difficulty, threads, checkpoint),
+        HashAlgorithm::Sha1 => find_nonce_parallel::<Sha1>(input, difficulty, threads, checkpoint),
+        HashAlgorithm::Sha256 => {
+            find_nonce_parallel::<Sha256>(input, difficulty, threads, checkpoint)
         }
     }
-    0
+}
+
+// Times the sequential and threaded searches against each other for both parts.
+fn bench(input: &str, threads: usize) {
+    for zeroes in [5, 6] {
+        let start = Instant::now();
+        let sequential = find_zeroes(input, zeroes);
+        let sequential_time = start.elapsed();
+
+        let start = Instant::now();
+        let parallel =
+            find_nonce_parallel::<Md5>(input, &Difficulty::ZeroBits(zeroes * 4), threads, None);
+        let parallel_time = start.elapsed();
+
+        println!("{} zeroes:", zeroes);
+        println!(
+            "  sequential:           {:?} in {:?}",
+            sequential, sequential_time
+        );
+        println!(
+            "  parallel ({} threads): {:?} in {:?} ({:.2}x)",
+            threads,
+            parallel,
+            parallel_time,
+            sequential_time.as_secs_f64() / parallel_time.as_secs_f64()
+        );
+    }
+}
+
+fn find_zeroes(input: &str, zeroes: usize) -> Option<u32> {
+    find_nonce::<Md5>(input, &Difficulty::ZeroBits(zeroes * 4))
+}
+
+fn find_nonce<D: Digest + Clone>(input: &str, difficulty: &Difficulty) -> Option<u32> {
+    find_all_nonces::<D>(input, difficulty, 0..SEARCH_LIMIT)
+        .next()
+        .map(|(nonce, _)| nonce)
+}
+
+fn find_all_nonces<'a, D: Digest + Clone>(
+    input: &str,
+    difficulty: &'a Difficulty,
+    nonces: Range<u32>,
+) -> Matches<'a, D> {
+    Matches {
+        tester: NonceTester::new(input, difficulty),
+        nonces,
+    }
+}
+
+// Every qualifying nonce in a range, in order, with its digest.
+struct Matches<'a, D> {
+    tester: NonceTester<'a, D>,
+    nonces: Range<u32>,
+}
+
+impl<D: Digest + Clone> Iterator for Matches<'_, D> {
+    type Item = (u32, Output<D>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nonces.find_map(|nonce| {
+            let digest = self.tester.digest(nonce);
+            self.tester
+                .difficulty
+                .is_met(&digest)
+                .then_some((nonce, digest))
+        })
+    }
+}
+
+// Workers claim chunks of candidates in increasing order and stop once their next chunk
+// starts beyond the best match so far, so every chunk below the smallest match is searched.
+// Meanwhile this thread reports progress and keeps the checkpoint up to date.
+fn find_nonce_parallel<D: Digest + Clone + Sync>(
+    input: &str,
+    difficulty: &Difficulty,
+    threads: usize,
+    checkpoint: Option<&Checkpoint>,
+) -> Option<u32> {
+    let tester = NonceTester::<D>::new(input, difficulty);
+    let start_chunk = checkpoint.map_or(0, |checkpoint| checkpoint.load() / CHUNK_SIZE);
+    let next_chunk = AtomicU32::new(start_chunk);
+    let best = AtomicU32::new(SEARCH_LIMIT);
+    let hashed = AtomicU64::new(0);
+    let completed = Mutex::new(CompletedChunks {
+        frontier: start_chunk,
+        pending: BTreeSet::new(),
+    });
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+                    let chunk_start = chunk.saturating_mul(CHUNK_SIZE);
+                    if chunk_start >= best.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let chunk_end = chunk_start.saturating_add(CHUNK_SIZE).min(SEARCH_LIMIT);
+                    let found = (chunk_start..chunk_end).find(|&i| tester.is_match(i));
+                    hashed.fetch_add(
+                        (found.unwrap_or(chunk_end) - chunk_start) as u64,
+                        Ordering::Relaxed,
+                    );
+                    match found {
+                        Some(i) => {
+                            best.fetch_min(i, Ordering::Relaxed);
+                        }
+                        None => completed.lock().unwrap().complete(chunk),
+                    }
+                })
+            })
+            .collect();
+
+        let mut last_report = (Instant::now(), 0);
+        while !workers.iter().all(|worker| worker.is_finished()) {
+            thread::sleep(Duration::from_millis(50));
+            let elapsed = last_report.0.elapsed();
+            if elapsed < PROGRESS_INTERVAL {
+                continue;
+            }
+            let frontier_nonce = completed
+                .lock()
+                .unwrap()
+                .frontier
+                .saturating_mul(CHUNK_SIZE);
+            let total_hashed = hashed.load(Ordering::Relaxed);
+            eprintln!(
+                "Searched up to nonce {} ({:.0} hashes/s)",
+                frontier_nonce,
+                (total_hashed - last_report.1) as f64 / elapsed.as_secs_f64()
+            );
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.save(frontier_nonce);
+            }
+            last_report = (Instant::now(), total_hashed);
+        }
+    });
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.clear();
+    }
+    match best.into_inner() {
+        SEARCH_LIMIT => None,
+        i => Some(i),
+    }
+}
+
+// Hashes candidates without allocating: the input is hashed once up front and each nonce
+// only adds its digits to a copy of that state.
+struct NonceTester<'a, D> {
+    prefix: D,
+    difficulty: &'a Difficulty,
+}
+
+impl<'a, D: Digest + Clone> NonceTester<'a, D> {
+    fn new(input: &str, difficulty: &'a Difficulty) -> Self {
+        let mut prefix = D::new();
+        prefix.update(input.as_bytes());
+        NonceTester { prefix, difficulty }
+    }
+
+    fn digest(&self, nonce: u32) -> Output<D> {
+        let mut buffer = [0; 10];
+        let mut hasher = self.prefix.clone();
+        hasher.update(format_nonce(nonce, &mut buffer));
+        hasher.finalize()
+    }
+
+    fn is_match(&self, nonce: u32) -> bool {
+        self.difficulty.is_met(&self.digest(nonce))
+    }
+}
+
+// Writes the decimal digits of `nonce` to the end of `buffer` and returns them.
+fn format_nonce(mut nonce: u32, buffer: &mut [u8; 10]) -> &[u8] {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (nonce % 10) as u8;
+        nonce /= 10;
+        if nonce == 0 {
+            break;
+        }
+    }
+    &buffer[start..]
+}
+
+fn has_leading_zero_bits(digest: &[u8], bits: usize) -> bool {
+    if bits > digest.len() * 8 {
+        return false;
+    }
+    let full_bytes = bits / 8;
+    digest[..full_bytes].iter().all(|&byte| byte == 0)
+        && (bits.is_multiple_of(8) || digest[full_bytes] >> (8 - bits % 8) == 0)
+}
+
+fn has_hex_prefix(digest: &[u8], nibbles: &[u8]) -> bool {
+    if nibbles.len() > digest.len() * 2 {
+        return false;
+    }
+    nibbles.iter().enumerate().all(|(i, &nibble)| {
+        let byte = digest[i / 2];
+        let digest_nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0xf };
+        digest_nibble == nibble
+    })
 }
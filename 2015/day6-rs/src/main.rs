@@ -1,28 +1,112 @@
 use itertools::iproduct;
-use regex::Regex;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::env::args;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::iter;
+use std::sync::OnceLock;
 
+#[derive(Clone, Copy)]
 struct Point(u32, u32);
 
+#[derive(Clone, Copy)]
 enum InstructionType {
     TurnOn,
     TurnOff,
     Toggle,
+    Set(u32),
+    Dim(u32),
+    Colour([u8; 3]),
 }
 
+#[derive(Clone, Copy)]
 struct Instruction {
     instruction_type: InstructionType,
     a: Point,
     b: Point,
 }
 
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let command = match self.instruction_type {
+            InstructionType::TurnOn => "turn on",
+            InstructionType::TurnOff => "turn off",
+            InstructionType::Toggle => "toggle",
+            InstructionType::Set(_) => "set",
+            InstructionType::Dim(_) => "dim",
+            InstructionType::Colour(_) => "colour",
+        };
+        write!(
+            f,
+            "{} {},{} through {},{}",
+            command, self.a.0, self.a.1, self.b.0, self.b.1
+        )?;
+        match self.instruction_type {
+            InstructionType::Set(level) => write!(f, " to {}", level),
+            InstructionType::Dim(amount) => write!(f, " by {}", amount),
+            InstructionType::Colour([r, g, b]) => write!(f, " #{:02x}{:02x}{:02x}", r, g, b),
+            _ => Ok(()),
+        }
+    }
+}
+
 trait Decoration {
     fn toggle_light(&mut self, x: u32, y: u32);
     fn turn_on_light(&mut self, x: u32, y: u32);
     fn turn_off_light(&mut self, x: u32, y: u32);
+    fn set_light(&mut self, x: u32, y: u32, level: u32);
+    fn dim_light(&mut self, x: u32, y: u32, amount: u32);
+    fn light_value(&self, x: u32, y: u32) -> u32;
+    fn dimensions(&self) -> (u32, u32);
     fn get_light_result(&self) -> u32;
 
-    fn process_inst(&mut self, inst: Instruction) {
+    // Lights without colour ignore colour instructions.
+    fn colour_light(&mut self, _x: u32, _y: u32, _colour: [u8; 3]) {}
+
+    fn has_colour(&self) -> bool {
+        false
+    }
+
+    fn light_colour(&self, _x: u32, _y: u32) -> [u8; 3] {
+        [255; 3]
+    }
+
+    // Total value of the lights in the rectangle from a to b.
+    fn sum_lights(&self, a: Point, b: Point) -> u64 {
+        rect_lights(a, b)
+            .map(|(x, y)| self.light_value(x, y) as u64)
+            .sum()
+    }
+
+    fn count_lit(&self, a: Point, b: Point) -> u64 {
+        rect_lights(a, b)
+            .filter(|&(x, y)| self.light_value(x, y) > 0)
+            .count() as u64
+    }
+
+    // The brightest light as (x, y, value), the first in row order on ties.
+    fn brightest_light(&self) -> (u32, u32, u32) {
+        let (width, height) = self.dimensions();
+        rect_lights(Point(0, 0), Point(width - 1, height - 1))
+            .map(|(x, y)| (x, y, self.light_value(x, y)))
+            .reduce(|best, light| if light.2 > best.2 { light } else { best })
+            .unwrap()
+    }
+
+    fn lit_regions(&self) -> Vec<Region> {
+        let (width, height) = self.dimensions();
+        find_regions(
+            width,
+            height,
+            |x, y| self.light_value(x, y) > 0,
+            |x, y| Region { x, y, size: 1 },
+        )
+    }
+
+    fn process_inst(&mut self, inst: &Instruction) {
         match inst.instruction_type {
             InstructionType::Toggle => {
                 for (x, y) in iproduct!(inst.a.0..=inst.b.0, inst.a.1..=inst.b.1) {
@@ -39,6 +123,21 @@ trait Decoration {
                     self.turn_off_light(x, y);
                 }
             }
+            InstructionType::Set(level) => {
+                for (x, y) in iproduct!(inst.a.0..=inst.b.0, inst.a.1..=inst.b.1) {
+                    self.set_light(x, y, level);
+                }
+            }
+            InstructionType::Dim(amount) => {
+                for (x, y) in iproduct!(inst.a.0..=inst.b.0, inst.a.1..=inst.b.1) {
+                    self.dim_light(x, y, amount);
+                }
+            }
+            InstructionType::Colour(colour) => {
+                for (x, y) in iproduct!(inst.a.0..=inst.b.0, inst.a.1..=inst.b.1) {
+                    self.colour_light(x, y, colour);
+                }
+            }
         };
     }
 }
@@ -52,7 +151,7 @@ impl DecorationV1 {
     fn new(width: u32, height: u32) -> Self {
         DecorationV1 {
             width,
-            lights: vec![false; (width * height) as usize],
+            lights: vec![false; width as usize * height as usize],
         }
     }
 }
@@ -73,6 +172,24 @@ impl Decoration for DecorationV1 {
         self.lights[i] = !self.lights[i]
     }
 
+    fn set_light(&mut self, x: u32, y: u32, level: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = level > 0;
+    }
+
+    fn dim_light(&mut self, x: u32, y: u32, amount: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = self.lights[i] && amount == 0;
+    }
+
+    fn light_value(&self, x: u32, y: u32) -> u32 {
+        self.lights[coord(self.width, x, y)] as u32
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.lights.len() as u32 / self.width)
+    }
+
     fn get_light_result(&self) -> u32 {
         let mut lit_count = 0;
         for light in &self.lights {
@@ -93,7 +210,7 @@ impl DecorationV2 {
     fn new(width: u32, height: u32) -> Self {
         DecorationV2 {
             width,
-            lights: vec![0; (width * height) as usize],
+            lights: vec![0; width as usize * height as usize],
         }
     }
 }
@@ -117,6 +234,24 @@ impl Decoration for DecorationV2 {
         self.lights[i] += 2
     }
 
+    fn set_light(&mut self, x: u32, y: u32, level: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = level;
+    }
+
+    fn dim_light(&mut self, x: u32, y: u32, amount: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = self.lights[i].saturating_sub(amount);
+    }
+
+    fn light_value(&self, x: u32, y: u32) -> u32 {
+        self.lights[coord(self.width, x, y)]
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.lights.len() as u32 / self.width)
+    }
+
     fn get_light_result(&self) -> u32 {
         let mut lit_count = 0;
         for light in &self.lights {
@@ -126,16 +261,716 @@ impl Decoration for DecorationV2 {
     }
 }
 
-fn process_input(input: &str, decoration: &mut impl Decoration) -> u32 {
-    input
-        .split("\n")
-        .map(|line| parse_line(line))
-        .for_each(|inst| decoration.process_inst(inst));
-    decoration.get_light_result()
+// Coloured bulbs for display mock-ups. Brightness follows the V2 rules and every bulb starts
+// out white.
+struct DecorationRgb {
+    width: u32,
+    lights: Vec<u32>,
+    colours: Vec<[u8; 3]>,
+}
+
+impl DecorationRgb {
+    fn new(width: u32, height: u32) -> Self {
+        DecorationRgb {
+            width,
+            lights: vec![0; width as usize * height as usize],
+            colours: vec![[255; 3]; width as usize * height as usize],
+        }
+    }
+}
+
+impl Decoration for DecorationRgb {
+    fn turn_on_light(&mut self, x: u32, y: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] += 1;
+    }
+
+    fn turn_off_light(&mut self, x: u32, y: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = self.lights[i].saturating_sub(1);
+    }
+
+    fn toggle_light(&mut self, x: u32, y: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] += 2
+    }
+
+    fn set_light(&mut self, x: u32, y: u32, level: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = level;
+    }
+
+    fn dim_light(&mut self, x: u32, y: u32, amount: u32) {
+        let i = coord(self.width, x, y);
+        self.lights[i] = self.lights[i].saturating_sub(amount);
+    }
+
+    fn colour_light(&mut self, x: u32, y: u32, colour: [u8; 3]) {
+        let i = coord(self.width, x, y);
+        self.colours[i] = colour;
+    }
+
+    fn light_value(&self, x: u32, y: u32) -> u32 {
+        self.lights[coord(self.width, x, y)]
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.lights.len() as u32 / self.width)
+    }
+
+    fn get_light_result(&self) -> u32 {
+        self.lights.iter().sum()
+    }
+
+    fn has_colour(&self) -> bool {
+        true
+    }
+
+    fn light_colour(&self, x: u32, y: u32) -> [u8; 3] {
+        self.colours[coord(self.width, x, y)]
+    }
+}
+
+// Lights grouped into blocks by coordinate compression. The grid is only split at the edges
+// of the instructions it is built for, so each of those instructions covers whole blocks and
+// every block is stored as a single light of the inner decoration. Operations on a single
+// light apply to the whole block containing it.
+struct SparseDecoration<D> {
+    // Block edges: block (i, j) spans columns xs[i]..xs[i + 1] and rows ys[j]..ys[j + 1].
+    xs: Vec<u32>,
+    ys: Vec<u32>,
+    blocks: D,
+}
+
+impl<D: Decoration> SparseDecoration<D> {
+    fn new(
+        width: u32,
+        height: u32,
+        instructions: &[Instruction],
+        new_blocks: impl FnOnce(u32, u32) -> D,
+    ) -> Self {
+        let mut xs = vec![0, width];
+        let mut ys = vec![0, height];
+        for inst in instructions {
+            xs.extend([inst.a.0, inst.b.0 + 1]);
+            ys.extend([inst.a.1, inst.b.1 + 1]);
+        }
+        xs.sort_unstable();
+        xs.dedup();
+        ys.sort_unstable();
+        ys.dedup();
+        let blocks = new_blocks(xs.len() as u32 - 1, ys.len() as u32 - 1);
+        SparseDecoration { xs, ys, blocks }
+    }
+
+    fn block(&self, x: u32, y: u32) -> (u32, u32) {
+        let bx = self.xs.partition_point(|&edge| edge <= x) - 1;
+        let by = self.ys.partition_point(|&edge| edge <= y) - 1;
+        (bx as u32, by as u32)
+    }
+
+    // The blocks overlapping the rectangle from a to b, with the number of their lights
+    // inside it.
+    fn blocks_within(&self, a: Point, b: Point) -> impl Iterator<Item = (u32, u32, u64)> + '_ {
+        let (ax, ay) = self.block(a.0, a.1);
+        let (bx, by) = self.block(b.0, b.1);
+        let overlap = |edges: &[u32], i: u32, lo: u32, hi: u32| {
+            let i = i as usize;
+            (edges[i + 1].min(hi + 1) - edges[i].max(lo)) as u64
+        };
+        iproduct!(ay..=by, ax..=bx).map(move |(y, x)| {
+            let area = overlap(&self.xs, x, a.0, b.0) * overlap(&self.ys, y, a.1, b.1);
+            (x, y, area)
+        })
+    }
+}
+
+impl<D: Decoration> Decoration for SparseDecoration<D> {
+    fn turn_on_light(&mut self, x: u32, y: u32) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.turn_on_light(bx, by);
+    }
+
+    fn turn_off_light(&mut self, x: u32, y: u32) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.turn_off_light(bx, by);
+    }
+
+    fn toggle_light(&mut self, x: u32, y: u32) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.toggle_light(bx, by);
+    }
+
+    fn set_light(&mut self, x: u32, y: u32, level: u32) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.set_light(bx, by, level);
+    }
+
+    fn dim_light(&mut self, x: u32, y: u32, amount: u32) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.dim_light(bx, by, amount);
+    }
+
+    fn colour_light(&mut self, x: u32, y: u32, colour: [u8; 3]) {
+        let (bx, by) = self.block(x, y);
+        self.blocks.colour_light(bx, by, colour);
+    }
+
+    fn has_colour(&self) -> bool {
+        self.blocks.has_colour()
+    }
+
+    fn light_colour(&self, x: u32, y: u32) -> [u8; 3] {
+        let (bx, by) = self.block(x, y);
+        self.blocks.light_colour(bx, by)
+    }
+
+    fn light_value(&self, x: u32, y: u32) -> u32 {
+        let (bx, by) = self.block(x, y);
+        self.blocks.light_value(bx, by)
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        (*self.xs.last().unwrap(), *self.ys.last().unwrap())
+    }
+
+    fn get_light_result(&self) -> u32 {
+        let mut lit_count = 0;
+        for (by, rows) in self.ys.windows(2).enumerate() {
+            for (bx, columns) in self.xs.windows(2).enumerate() {
+                let area = (columns[1] - columns[0]) * (rows[1] - rows[0]);
+                lit_count += self.blocks.light_value(bx as u32, by as u32) * area;
+            }
+        }
+        lit_count
+    }
+
+    fn sum_lights(&self, a: Point, b: Point) -> u64 {
+        self.blocks_within(a, b)
+            .map(|(bx, by, area)| self.blocks.light_value(bx, by) as u64 * area)
+            .sum()
+    }
+
+    fn count_lit(&self, a: Point, b: Point) -> u64 {
+        self.blocks_within(a, b)
+            .filter(|&(bx, by, _)| self.blocks.light_value(bx, by) > 0)
+            .map(|(_, _, area)| area)
+            .sum()
+    }
+
+    // Blocks are ordered the same way as their top left lights, so the brightest block's
+    // corner is the first brightest light.
+    fn brightest_light(&self) -> (u32, u32, u32) {
+        let (bx, by, value) = self.blocks.brightest_light();
+        (self.xs[bx as usize], self.ys[by as usize], value)
+    }
+
+    fn lit_regions(&self) -> Vec<Region> {
+        let (width, height) = self.blocks.dimensions();
+        find_regions(
+            width,
+            height,
+            |bx, by| self.blocks.light_value(bx, by) > 0,
+            |bx, by| {
+                let (bx, by) = (bx as usize, by as usize);
+                Region {
+                    x: self.xs[bx],
+                    y: self.ys[by],
+                    size: (self.xs[bx + 1] - self.xs[bx]) as u64
+                        * (self.ys[by + 1] - self.ys[by]) as u64,
+                }
+            },
+        )
+    }
+
+    fn process_inst(&mut self, inst: &Instruction) {
+        let (ax, ay) = self.block(inst.a.0, inst.a.1);
+        let (bx, by) = self.block(inst.b.0, inst.b.1);
+        self.blocks.process_inst(&Instruction {
+            instruction_type: inst.instruction_type,
+            a: Point(ax, ay),
+            b: Point(bx, by),
+        });
+    }
+}
+
+// A connected group of lit lights, identified by its first light in row order.
+struct Region {
+    x: u32,
+    y: u32,
+    size: u64,
+}
+
+// Flood fills the lit cells of a width x height grid, joining cells that share an edge. Each
+// cell contributes the region returned by `cell` for it, so cells can stand for more than one
+// light.
+fn find_regions(
+    width: u32,
+    height: u32,
+    is_lit: impl Fn(u32, u32) -> bool,
+    cell: impl Fn(u32, u32) -> Region,
+) -> Vec<Region> {
+    let mut visited = vec![false; width as usize * height as usize];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for (y, x) in iproduct!(0..height, 0..width) {
+        if visited[coord(width, x, y)] || !is_lit(x, y) {
+            continue;
+        }
+        let mut region = cell(x, y);
+        region.size = 0;
+        visited[coord(width, x, y)] = true;
+        stack.push((x, y));
+        while let Some((x, y)) = stack.pop() {
+            region.size += cell(x, y).size;
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx < width && ny < height && !visited[coord(width, nx, ny)] && is_lit(nx, ny) {
+                    visited[coord(width, nx, ny)] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+enum Query {
+    Count(Point, Point),
+    Sum(Point, Point),
+    Regions,
+    Brightest,
+}
+
+// Parses 'query <part> <query> [x1,y1 x2,y2]', returning the part to run and the remaining
+// arguments.
+fn parse_query(args: &[String]) -> Result<(&str, Query, &[String]), String> {
+    const USAGE: &str = "Please specify a part and a query, e.g. 'query part2 sum 0,0 999,999', \
+                         'query part1 count 0,0 499,499', 'query part1 regions' or \
+                         'query part2 brightest'";
+    let (part, kind) = match args {
+        [part, kind, ..] => (part.as_str(), kind.as_str()),
+        _ => return Err(USAGE.to_string()),
+    };
+    let parse_rect = || match &args[2..] {
+        [a, b, ..] => match (parse_point(a), parse_point(b)) {
+            (Some(a), Some(b)) => Ok((a, b)),
+            _ => Err(format!("Invalid rectangle '{} {}'.", a, b)),
+        },
+        _ => Err(USAGE.to_string()),
+    };
+    match kind {
+        "count" => parse_rect().map(|(a, b)| (part, Query::Count(a, b), &args[4..])),
+        "sum" => parse_rect().map(|(a, b)| (part, Query::Sum(a, b), &args[4..])),
+        "regions" => Ok((part, Query::Regions, &args[2..])),
+        "brightest" => Ok((part, Query::Brightest, &args[2..])),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_point(point: &str) -> Option<Point> {
+    let (x, y) = point.split_once(',')?;
+    Some(Point(x.parse().ok()?, y.parse().ok()?))
+}
+
+fn run_query(decoration: &impl Decoration, query: &Query) -> Result<String, String> {
+    match *query {
+        Query::Count(a, b) | Query::Sum(a, b) => {
+            let (width, height) = decoration.dimensions();
+            if a.0 > b.0 || a.1 > b.1 || b.0 >= width || b.1 >= height {
+                return Err(format!(
+                    "Rectangle {},{} {},{} is not within the {}x{} grid.",
+                    a.0, a.1, b.0, b.1, width, height
+                ));
+            }
+            let result = match query {
+                Query::Count(..) => decoration.count_lit(a, b),
+                _ => decoration.sum_lights(a, b),
+            };
+            Ok(result.to_string())
+        }
+        Query::Regions => {
+            let mut regions = decoration.lit_regions();
+            regions.sort_by_key(|region| Reverse(region.size));
+            let mut output = format!("{} regions", regions.len());
+            for region in regions {
+                output += &format!("\n{},{}: {}", region.x, region.y, region.size);
+            }
+            Ok(output)
+        }
+        Query::Brightest => {
+            let (x, y, value) = decoration.brightest_light();
+            Ok(format!("{},{}: {}", x, y, value))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Palette {
+    Grayscale,
+    // Black through red and yellow to white.
+    Heat,
+}
+
+impl Palette {
+    fn colour(&self, level: u8) -> [u8; 3] {
+        let t = level as f64 / 255.0;
+        let channel = |t: f64| (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self {
+            Palette::Grayscale => [level; 3],
+            Palette::Heat => [
+                channel(3.0 * t),
+                channel(3.0 * t - 1.0),
+                channel(3.0 * t - 2.0),
+            ],
+        }
+    }
+
+    // RGB bytes for every level, as used for a GIF colour table.
+    fn colour_table(&self) -> Vec<u8> {
+        (0..=255).flat_map(|level| self.colour(level)).collect()
+    }
+}
+
+// Pixels row by row.
+enum Pixels {
+    // Palette level of each pixel, for lights without colour.
+    Levels(Vec<u8>, Palette),
+    // RGB bytes, for coloured bulbs.
+    Rgb(Vec<u8>),
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+impl Image {
+    // One pixel per light, scaled so the brightest light is the top of the palette. On/off
+    // lights come out black and white, and coloured bulbs are shown in their own colour.
+    fn render(decoration: &impl Decoration, palette: Palette) -> Self {
+        let (width, height) = decoration.dimensions();
+        let lights = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+        let values: Vec<u32> = lights()
+            .map(|(x, y)| decoration.light_value(x, y))
+            .collect();
+        let max_value = values.iter().copied().max().unwrap_or(0).max(1) as u64;
+        let levels = values
+            .into_iter()
+            .map(|value| ((value as u64 * 255 + max_value / 2) / max_value) as u8);
+        let pixels = if decoration.has_colour() {
+            Pixels::Rgb(
+                lights()
+                    .zip(levels)
+                    .flat_map(|((x, y), level)| {
+                        decoration
+                            .light_colour(x, y)
+                            .map(|channel| (channel as u32 * level as u32 / 255) as u8)
+                    })
+                    .collect(),
+            )
+        } else {
+            Pixels::Levels(levels.collect(), palette)
+        };
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn rgb(&self) -> Cow<'_, [u8]> {
+        match &self.pixels {
+            Pixels::Levels(levels, palette) => levels
+                .iter()
+                .flat_map(|&level| palette.colour(level))
+                .collect(),
+            Pixels::Rgb(rgb) => Cow::Borrowed(rgb),
+        }
+    }
+
+    // Writes a PNG if the path ends in ".png" and a binary PPM otherwise.
+    fn save(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        if path.ends_with(".png") {
+            let mut encoder = png::Encoder::new(writer, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .write_header()
+                .and_then(|mut png_writer| png_writer.write_image_data(&self.rgb()))
+                .map_err(|e| format!("Failed to write {}: {}", path, e))
+        } else {
+            write!(writer, "P6\n{} {}\n255\n", self.width, self.height)
+                .and_then(|_| writer.write_all(&self.rgb()))
+                .and_then(|_| writer.flush())
+                .map_err(|e| format!("Failed to write {}: {}", path, e))
+        }
+    }
+}
+
+// Snapshots of the grid as an animated GIF, or as numbered images when the path is not a GIF
+// ("frames.png" becomes "frames-0000.png", "frames-0001.png", ...). Each frame is scaled to
+// its own brightest light.
+enum Animation {
+    Gif {
+        path: String,
+        encoder: gif::Encoder<BufWriter<File>>,
+    },
+    Frames {
+        path: String,
+        count: usize,
+    },
+}
+
+impl Animation {
+    fn create(path: &str, width: u32, height: u32, palette: Palette) -> Result<Self, String> {
+        if !path.ends_with(".gif") {
+            return Ok(Animation::Frames {
+                path: path.to_string(),
+                count: 0,
+            });
+        }
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(format!(
+                "The grid is too large for a GIF: {}x{}.",
+                width, height
+            ));
+        };
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut encoder =
+            gif::Encoder::new(BufWriter::new(file), width, height, &palette.colour_table())
+                .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(Animation::Gif {
+            path: path.to_string(),
+            encoder,
+        })
+    }
+
+    fn add_frame(&mut self, image: &Image) -> Result<(), String> {
+        match self {
+            Animation::Gif { path, encoder } => {
+                let (width, height) = (image.width as u16, image.height as u16);
+                let mut frame = match &image.pixels {
+                    Pixels::Levels(levels, _) => gif::Frame {
+                        width,
+                        height,
+                        buffer: Cow::Borrowed(levels),
+                        ..gif::Frame::default()
+                    },
+                    Pixels::Rgb(rgb) => gif::Frame::from_rgb_speed(width, height, rgb, 10),
+                };
+                frame.delay = 10;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|e| format!("Failed to write {}: {}", path, e))
+            }
+            Animation::Frames { path, count } => {
+                let frame_path = match path.rsplit_once('.') {
+                    Some((stem, extension)) => format!("{}-{:04}.{}", stem, count, extension),
+                    None => format!("{}-{:04}", path, count),
+                };
+                *count += 1;
+                image.save(&frame_path)
+            }
+        }
+    }
+}
+
+fn parse_input(input: &str) -> Vec<Instruction> {
+    input.split("\n").map(parse_line).collect()
+}
+
+// The smallest grid covering every instruction.
+fn infer_grid_size(instructions: &[Instruction]) -> (u32, u32) {
+    instructions.iter().fold((1, 1), |(width, height), inst| {
+        (
+            width.max(inst.a.0 + 1).max(inst.b.0 + 1),
+            height.max(inst.a.1 + 1).max(inst.b.1 + 1),
+        )
+    })
+}
+
+// Rejects rectangles with their corners reversed or reaching outside the grid. When
+// normalizing, reversed corners are swapped and rectangles are clipped to the grid instead,
+// dropping any that miss it entirely.
+fn check_bounds(
+    instructions: Vec<Instruction>,
+    width: u32,
+    height: u32,
+    normalize: bool,
+) -> Result<Vec<Instruction>, String> {
+    let mut checked = Vec::new();
+    for (line, mut inst) in instructions.into_iter().enumerate() {
+        let describe = |inst: &Instruction| {
+            format!(
+                "Line {}: rectangle {},{} through {},{}",
+                line + 1,
+                inst.a.0,
+                inst.a.1,
+                inst.b.0,
+                inst.b.1
+            )
+        };
+        if inst.a.0 > inst.b.0 || inst.a.1 > inst.b.1 {
+            if !normalize {
+                return Err(format!("{} is reversed.", describe(&inst)));
+            }
+            (inst.a, inst.b) = (
+                Point(inst.a.0.min(inst.b.0), inst.a.1.min(inst.b.1)),
+                Point(inst.a.0.max(inst.b.0), inst.a.1.max(inst.b.1)),
+            );
+        }
+        if inst.b.0 >= width || inst.b.1 >= height {
+            if !normalize {
+                return Err(format!(
+                    "{} is outside the {}x{} grid.",
+                    describe(&inst),
+                    width,
+                    height
+                ));
+            }
+            if inst.a.0 >= width || inst.a.1 >= height {
+                continue;
+            }
+            inst.b = Point(inst.b.0.min(width - 1), inst.b.1.min(height - 1));
+        }
+        checked.push(inst);
+    }
+    Ok(checked)
+}
+
+// What a single instruction changed.
+struct InstructionStats {
+    instruction: Instruction,
+    turned_on: u32,
+    turned_off: u32,
+    brightness_delta: i64,
+}
+
+// The lights an instruction covered as they were before it, as runs of (count, value, colour)
+// in row order.
+struct UndoEntry {
+    a: Point,
+    b: Point,
+    runs: Vec<(u32, u32, [u8; 3])>,
+}
+
+// Applies instructions while recording what each one changed, so they can be reported on and
+// undone again in reverse order.
+#[derive(Default)]
+struct InstructionLog {
+    stats: Vec<InstructionStats>,
+    undo_entries: Vec<UndoEntry>,
+}
+
+impl InstructionLog {
+    fn apply(&mut self, decoration: &mut impl Decoration, inst: &Instruction) {
+        let mut runs: Vec<(u32, u32, [u8; 3])> = Vec::new();
+        for (x, y) in rect_lights(inst.a, inst.b) {
+            let (value, colour) = (decoration.light_value(x, y), decoration.light_colour(x, y));
+            match runs.last_mut() {
+                Some(run) if (run.1, run.2) == (value, colour) => run.0 += 1,
+                _ => runs.push((1, value, colour)),
+            }
+        }
+
+        decoration.process_inst(inst);
+
+        let mut stats = InstructionStats {
+            instruction: *inst,
+            turned_on: 0,
+            turned_off: 0,
+            brightness_delta: 0,
+        };
+        let before = runs
+            .iter()
+            .flat_map(|&(count, value, _)| iter::repeat_n(value, count as usize));
+        for ((x, y), before) in rect_lights(inst.a, inst.b).zip(before) {
+            let after = decoration.light_value(x, y);
+            if before == 0 && after > 0 {
+                stats.turned_on += 1;
+            } else if before > 0 && after == 0 {
+                stats.turned_off += 1;
+            }
+            stats.brightness_delta += after as i64 - before as i64;
+        }
+        self.stats.push(stats);
+        self.undo_entries.push(UndoEntry {
+            a: inst.a,
+            b: inst.b,
+            runs,
+        });
+    }
+
+    // Restores the lights covered by the most recent instruction still applied.
+    fn undo(&mut self, decoration: &mut impl Decoration) -> bool {
+        let Some(entry) = self.undo_entries.pop() else {
+            return false;
+        };
+        let before = entry
+            .runs
+            .iter()
+            .flat_map(|&(count, value, colour)| iter::repeat_n((value, colour), count as usize));
+        for ((x, y), (value, colour)) in rect_lights(entry.a, entry.b).zip(before) {
+            decoration.set_light(x, y, value);
+            decoration.colour_light(x, y, colour);
+        }
+        true
+    }
+
+    fn save_stats(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        let mut write_report = || {
+            writeln!(
+                writer,
+                "instruction,command,turned_on,turned_off,brightness_delta"
+            )?;
+            for (i, stats) in self.stats.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "{},\"{}\",{},{},{}",
+                    i + 1,
+                    stats.instruction,
+                    stats.turned_on,
+                    stats.turned_off,
+                    stats.brightness_delta
+                )?;
+            }
+            writer.flush()
+        };
+        write_report().map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+}
+
+fn rect_lights(a: Point, b: Point) -> impl Iterator<Item = (u32, u32)> {
+    iproduct!(a.1..=b.1, a.0..=b.0).map(|(y, x)| (x, y))
 }
 
 fn parse_line(line: &str) -> Instruction {
-    let re = Regex::new(r"(turn|toggle) (on |off |)(\d+),(\d+) through (\d+),(\d+)").unwrap();
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static EXTENDED_RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"^(turn|toggle) (on |off |)(\d+),(\d+) through (\d+),(\d+)$").unwrap()
+    });
+    let extended_re = EXTENDED_RE.get_or_init(|| {
+        Regex::new(r"^(set|dim|colour) (\d+),(\d+) through (\d+),(\d+) (to |by |#)(\w+)$").unwrap()
+    });
+    if let Some(captures) = extended_re.captures(line) {
+        return parse_extended_instruction(&captures);
+    }
     let captures = re.captures(line).unwrap();
     let a = Point(
         captures[3].parse::<u32>().unwrap(),
@@ -171,8 +1006,36 @@ fn parse_line(line: &str) -> Instruction {
     }
 }
 
+// `set ... to <level>`, `dim ... by <amount>` and `colour ... #rrggbb`.
+fn parse_extended_instruction(captures: &Captures) -> Instruction {
+    let a = Point(
+        captures[2].parse::<u32>().unwrap(),
+        captures[3].parse::<u32>().unwrap(),
+    );
+    let b = Point(
+        captures[4].parse::<u32>().unwrap(),
+        captures[5].parse::<u32>().unwrap(),
+    );
+    let arg = &captures[7];
+    let instruction_type = match (&captures[1], &captures[6]) {
+        ("set", "to ") => InstructionType::Set(arg.parse().expect("Invalid level.")),
+        ("dim", "by ") => InstructionType::Dim(arg.parse().expect("Invalid amount.")),
+        ("colour", "#") if arg.len() == 6 => {
+            let rgb = u32::from_str_radix(arg, 16).expect("Invalid colour.");
+            InstructionType::Colour([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+        }
+        _ => panic!("Invalid argument."),
+    };
+    Instruction {
+        instruction_type,
+        a,
+        b,
+    }
+}
+
 fn coord(width: u32, x: u32, y: u32) -> usize {
-    (y * width + x) as usize
+    debug_assert!(x < width, "x = {} is outside a grid {} wide", x, width);
+    y as usize * width as usize + x as usize
 }
 
 fn load_input() -> String {
@@ -181,32 +1044,239 @@ fn load_input() -> String {
     String::from(read_to_string("./input.txt").unwrap().trim())
 }
 
-fn part1(input: &str) -> u32 {
-    let mut decoration = DecorationV1::new(1000, 1000);
-    process_input(input, &mut decoration)
+enum GridSize {
+    Fixed(u32, u32),
+    Inferred,
+}
+
+struct Options {
+    size: GridSize,
+    normalize: bool,
+    sparse: bool,
+    render: Option<String>,
+    animate: Option<String>,
+    frame_interval: usize,
+    palette: Palette,
+    stats: Option<String>,
+    undo: usize,
+    query: Option<Query>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        size: GridSize::Fixed(1000, 1000),
+        normalize: false,
+        sparse: false,
+        render: None,
+        animate: None,
+        frame_interval: 10,
+        palette: Palette::Grayscale,
+        stats: None,
+        undo: 0,
+        query: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let size = args.next().ok_or(
+                    "Please specify a grid size, e.g. '--size 1000x1000' or '--size auto'",
+                )?;
+                options.size = match size.split_once('x') {
+                    _ if size == "auto" => GridSize::Inferred,
+                    Some((width, height)) => match (width.parse(), height.parse()) {
+                        (Ok(width), Ok(height)) if width > 0 && height > 0 => {
+                            GridSize::Fixed(width, height)
+                        }
+                        _ => return Err(format!("Invalid grid size '{}'.", size)),
+                    },
+                    None => return Err(format!("Invalid grid size '{}'.", size)),
+                };
+            }
+            "--normalize" => options.normalize = true,
+            "--sparse" => options.sparse = true,
+            "--render" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify an image file, e.g. '--render out.png'")?;
+                options.render = Some(path.clone());
+            }
+            "--animate" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify an animation file, e.g. '--animate out.gif'")?;
+                options.animate = Some(path.clone());
+            }
+            "--every" => {
+                options.frame_interval = args
+                    .next()
+                    .and_then(|interval| interval.parse().ok())
+                    .filter(|&interval| interval > 0)
+                    .ok_or("Please specify a number of instructions, e.g. '--every 10'")?;
+            }
+            "--palette" => {
+                options.palette = match args.next().map(|palette| palette.as_str()) {
+                    Some("gray") => Palette::Grayscale,
+                    Some("heat") => Palette::Heat,
+                    _ => return Err("Please specify 'gray' or 'heat'".to_string()),
+                };
+            }
+            "--stats" => {
+                let path = args
+                    .next()
+                    .ok_or("Please specify a CSV file, e.g. '--stats stats.csv'")?;
+                options.stats = Some(path.clone());
+            }
+            "--undo" => {
+                options.undo = args
+                    .next()
+                    .and_then(|undo| undo.parse().ok())
+                    .ok_or("Please specify a number of instructions, e.g. '--undo 5'")?;
+            }
+            _ => return Err(format!("Unknown option '{}'.", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn part1(input: &str, options: &Options) -> Result<String, String> {
+    let (instructions, width, height) = prepare_instructions(input, options)?;
+    if options.sparse {
+        let mut decoration = SparseDecoration::new(width, height, &instructions, DecorationV1::new);
+        decorate(&instructions, &mut decoration, options)
+    } else {
+        let mut decoration = DecorationV1::new(width, height);
+        decorate(&instructions, &mut decoration, options)
+    }
+}
+
+fn part2(input: &str, options: &Options) -> Result<String, String> {
+    let (instructions, width, height) = prepare_instructions(input, options)?;
+    if options.sparse {
+        let mut decoration = SparseDecoration::new(width, height, &instructions, DecorationV2::new);
+        decorate(&instructions, &mut decoration, options)
+    } else {
+        let mut decoration = DecorationV2::new(width, height);
+        decorate(&instructions, &mut decoration, options)
+    }
+}
+
+// Parses the instructions and checks them against the grid they will be applied to.
+fn prepare_instructions(
+    input: &str,
+    options: &Options,
+) -> Result<(Vec<Instruction>, u32, u32), String> {
+    let instructions = parse_input(input);
+    let (width, height) = match options.size {
+        GridSize::Fixed(width, height) => (width, height),
+        GridSize::Inferred => infer_grid_size(&instructions),
+    };
+    let instructions = check_bounds(instructions, width, height, options.normalize)?;
+    Ok((instructions, width, height))
 }
 
-fn part2(input: &str) -> u32 {
-    let mut decoration = DecorationV2::new(1000, 1000);
-    process_input(input, &mut decoration)
+fn display(input: &str, options: &Options) -> Result<String, String> {
+    let (instructions, width, height) = prepare_instructions(input, options)?;
+    if options.sparse {
+        let mut decoration =
+            SparseDecoration::new(width, height, &instructions, DecorationRgb::new);
+        decorate(&instructions, &mut decoration, options)
+    } else {
+        let mut decoration = DecorationRgb::new(width, height);
+        decorate(&instructions, &mut decoration, options)
+    }
+}
+
+fn decorate(
+    instructions: &[Instruction],
+    decoration: &mut impl Decoration,
+    options: &Options,
+) -> Result<String, String> {
+    if options.undo > instructions.len() {
+        return Err(format!(
+            "Can't undo {} of {} instructions.",
+            options.undo,
+            instructions.len()
+        ));
+    }
+    let mut animation = match &options.animate {
+        Some(path) => {
+            let (width, height) = decoration.dimensions();
+            Some(Animation::create(path, width, height, options.palette)?)
+        }
+        None => None,
+    };
+    let mut log = (options.stats.is_some() || options.undo > 0).then(InstructionLog::default);
+
+    for (i, inst) in instructions.iter().enumerate() {
+        match &mut log {
+            Some(log) => log.apply(decoration, inst),
+            None => decoration.process_inst(inst),
+        }
+        if let Some(animation) = &mut animation {
+            if (i + 1) % options.frame_interval == 0 || i + 1 == instructions.len() {
+                animation.add_frame(&Image::render(decoration, options.palette))?;
+            }
+        }
+    }
+
+    if let Some(log) = &mut log {
+        if let Some(path) = &options.stats {
+            log.save_stats(path)?;
+        }
+        // Step back through the last instructions, adding a frame for each.
+        for _ in 0..options.undo {
+            log.undo(decoration);
+            if let Some(animation) = &mut animation {
+                animation.add_frame(&Image::render(decoration, options.palette))?;
+            }
+        }
+    }
+
+    if let Some(path) = &options.render {
+        Image::render(decoration, options.palette).save(path)?;
+    }
+    match &options.query {
+        Some(query) => run_query(decoration, query),
+        None => Ok(decoration.get_light_result().to_string()),
+    }
 }
 
 fn main() {
     let pargs = Vec::from_iter(args());
-    if pargs.len() != 2 {
-        eprintln!("Please specify 'part1' or 'part2'");
+    if pargs.len() < 2 {
+        eprintln!("Please specify 'part1', 'part2', 'display' or 'query'");
         return;
     }
-    let input = load_input();
-    match pargs[1].as_str() {
-        "part1" => {
-            println!("{}", part1(&input))
-        }
-        "part2" => {
-            println!("{}", part2(&input))
+    let (command, query, option_args) = match pargs[1].as_str() {
+        "query" => match parse_query(&pargs[2..]) {
+            Ok((part, query, option_args)) => (part, Some(query), option_args),
+            Err(message) => {
+                eprintln!("{}", message);
+                return;
+            }
+        },
+        command => (command, None, &pargs[2..]),
+    };
+    let options = match parse_options(option_args) {
+        Ok(options) => Options { query, ..options },
+        Err(message) => {
+            eprintln!("{}", message);
+            return;
         }
+    };
+    let input = load_input();
+    let result = match command {
+        "part1" => part1(&input, &options),
+        "part2" => part2(&input, &options),
+        "display" => display(&input, &options),
         _ => {
-            eprintln!("Invalid argument.")
+            eprintln!("Invalid argument.");
+            return;
         }
     };
+    match result {
+        Ok(result) => println!("{}", result),
+        Err(message) => eprintln!("{}", message),
+    };
 }